@@ -1,4 +1,12 @@
+use crate::bootstrap;
 use crate::error;
+use crate::filter;
+use crate::identity;
+use crate::journal;
+use crate::message;
+use crate::redact;
+use crate::secrets;
+use crate::util;
 use serde::Deserialize;
 
 pub struct Branch {
@@ -8,6 +16,43 @@ pub struct Branch {
     pub refname: String,
 }
 
+impl Branch {
+    pub fn new(name: String) -> Self {
+        let refname = format!("refs/heads/{}", name);
+        Self { name, refname }
+    }
+
+    /// Reference on which the copies are created, until every branch is synchronized
+    pub fn staging_refname(&self) -> String {
+        format!("refs/ripit/staging/{}", self.name)
+    }
+}
+
+/// Command to run, other than synchronizing or bootstrapping the branches
+#[derive(PartialEq)]
+pub enum Command {
+    Sync,
+    // put the branches back where they were before the last synchronization
+    Rollback,
+    // print the entries of the journal
+    Log(journal::LogFilter),
+    // print the counterpart of a commit
+    Map(String),
+    // print the commits left to synchronize on every branch
+    Status,
+    // append a bootstrap commit on a branch, to skip the remote commits not yet synchronized
+    Rebootstrap(String),
+}
+
+/// Format of the output
+#[derive(Clone, Copy, PartialEq)]
+pub enum Output {
+    // messages for the user
+    Text,
+    // JSON events for automation, the messages for the user are printed on stderr
+    Json,
+}
+
 pub struct Options {
     // path to the local repo
     pub repo: String,
@@ -18,14 +63,60 @@ pub struct Options {
     pub branches: Vec<Branch>,
 
     pub commit_msg_filters: regex::RegexSet,
+    pub commit_msg_blocks: Vec<message::BlockFilter>,
+    pub commit_msg_trailers: Vec<String>,
+    pub commit_msg_rewrites: Vec<message::Rewrite>,
+    pub unknown_commit_ids: message::UnknownCommitIds,
+    // compiled once, as it is matched against every copied message
+    pub commit_id_regex: regex::Regex,
 
+    // rewriting of authors and committers of copied commits
+    pub identities: identity::IdentityRewriter,
+
+    // identities, date and message of the bootstrap commits
+    pub bootstrap_template: bootstrap::BootstrapTemplate,
+
+    // filtering of the trees of copied commits
+    pub tree_filter: filter::TreeFilter,
+    pub empty_commits: filter::EmptyCommits,
+
+    // scanning of the commits to synchronize
+    pub secrets: secrets::SecretScanner,
+
+    // mainlines of merge commits, overriding the ones computed from the mapped parents
+    pub mainlines: Vec<MainlineCfg>,
+
+    pub command: Command,
+    // hash of the configuration file, recorded in the journal
+    pub config_hash: String,
     pub bootstrap: bool,
+    // revision or date of the remote commits to bootstrap from, instead of the branches' tips
+    pub bootstrap_at: Option<String>,
+    // copy the whole history of the branches that are not bootstrapped
+    pub full_history: bool,
     pub uproot: bool,
     pub verbose: bool,
     pub yes: bool,
+    pub dry_run: bool,
+    // commit the resolution of conflicts, then carry on with the synchronization
+    pub continue_sync: bool,
+    // restore the repository as it was before the synchronization in progress
+    pub abort: bool,
+    // copy the commits without using the working directory of the repository
+    pub worktree: bool,
+    pub output: Output,
     pub fetch: bool,
 }
 
+/// Parent to use as mainline when copying a merge commit
+#[derive(Deserialize)]
+pub struct MainlineCfg {
+    // id of the remote merge commit, or a prefix of it
+    pub commit: String,
+    // number of the parent, starting from 1
+    pub parent: usize,
+}
+
 #[derive(Deserialize)]
 struct YamlCfg {
     repo: Option<String>,
@@ -34,17 +125,90 @@ struct YamlCfg {
     branch: Option<String>,
     branches: Option<Vec<String>>,
     filters: Option<Vec<String>>,
+    block_filters: Option<Vec<message::BlockFilterCfg>>,
+    trailer_filters: Option<Vec<String>>,
+    rewrites: Option<Vec<message::RewriteCfg>>,
+    unknown_commit_ids: Option<message::UnknownCommitIds>,
+    identities: Option<identity::IdentitiesCfg>,
+    bootstrap: Option<bootstrap::BootstrapCfg>,
+    include_paths: Option<Vec<String>>,
+    exclude_paths: Option<Vec<String>>,
+    path_mappings: Option<Vec<filter::PathMapping>>,
+    redactions: Option<Vec<redact::RedactionCfg>>,
+    secrets: Option<secrets::SecretsCfg>,
+    empty_commits: Option<filter::EmptyCommits>,
+    mainlines: Option<Vec<MainlineCfg>>,
 }
 
-pub fn parse_args() -> Result<Options, error::Error> {
+fn parse_log_filter(matches: &clap::ArgMatches) -> Result<journal::LogFilter, error::Error> {
+    let date = |name| match matches.value_of(name) {
+        Some(value) if util::parse_date(value).is_none() => Err(error::Error::InvalidDate {
+            date: value.to_owned(),
+        }),
+        value => Ok(value.map(|s| s.to_owned())),
+    };
+
+    Ok(journal::LogFilter {
+        since: date("since")?,
+        until: date("until")?,
+        branch: matches.value_of("branch").map(|s| s.to_owned()),
+        commit: matches.value_of("commit").map(|s| s.to_owned()),
+    })
+}
+
+/// Parse the command line and the configuration file
+///
+/// The format of the output is returned even if the options are invalid, so that the error can
+/// be reported in this format.
+pub fn parse_args() -> (Output, Result<Options, error::Error>) {
     let yaml = clap::load_yaml!("cli.yml");
     let matches = clap::App::from_yaml(yaml)
         .setting(clap::AppSettings::ColoredHelp)
         .get_matches();
 
-    let path = matches.value_of("config_file").unwrap();
-    let file = match std::fs::File::open(path) {
-        Ok(f) => f,
+    let output = match matches.value_of("output") {
+        Some("json") => Output::Json,
+        _ => Output::Text,
+    };
+    (output, parse_options(&matches, output))
+}
+
+fn parse_options(matches: &clap::ArgMatches, output: Output) -> Result<Options, error::Error> {
+    let (command, sub_matches) = match matches.subcommand() {
+        ("rollback", Some(sub_matches)) => (Command::Rollback, sub_matches),
+        ("log", Some(sub_matches)) => (Command::Log(parse_log_filter(sub_matches)?), sub_matches),
+        ("map", Some(sub_matches)) => {
+            let rev = sub_matches.value_of("commit").unwrap().to_owned();
+            (Command::Map(rev), sub_matches)
+        }
+        ("status", Some(sub_matches)) => (Command::Status, sub_matches),
+        ("rebootstrap", Some(sub_matches)) => {
+            let branch = sub_matches.value_of("branch").unwrap().to_owned();
+            (Command::Rebootstrap(branch), sub_matches)
+        }
+        _ => (Command::Sync, matches),
+    };
+    if let Some(name) = matches.subcommand_name() {
+        // the options of the synchronization would otherwise be silently ignored
+        let sync_options = [
+            ("bootstrap", "--bootstrap"),
+            ("bootstrap_at", "--bootstrap-at"),
+            ("full_history", "--full-history"),
+            ("dry_run", "--dry-run"),
+            ("continue", "--continue"),
+            ("abort", "--abort"),
+        ];
+        if let Some((_, option)) = sync_options.iter().find(|(arg, _)| matches.is_present(arg)) {
+            return Err(error::Error::OptionNotSupported {
+                option,
+                command: name.to_owned(),
+            });
+        }
+    }
+
+    let path = sub_matches.value_of("config_file").unwrap();
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
         Err(error) => {
             return Err(error::Error::FailedOpenCfg {
                 path: path.to_owned(),
@@ -52,8 +216,9 @@ pub fn parse_args() -> Result<Options, error::Error> {
             })
         }
     };
+    let config_hash = git2::Oid::hash_object(git2::ObjectType::Blob, content.as_bytes())?;
 
-    let cfg: YamlCfg = match serde_yaml::from_reader(file) {
+    let cfg: YamlCfg = match serde_yaml::from_str(&content) {
         Ok(cfg) => cfg,
         Err(error) => {
             return Err(error::Error::FailedParseCfg {
@@ -65,16 +230,10 @@ pub fn parse_args() -> Result<Options, error::Error> {
     // backward compatibility on legacy branch option
     let branch = cfg.branch.unwrap_or("master".to_owned());
     let mut branches = cfg.branches.unwrap_or(vec![]);
-    if branches.len() == 0 {
+    if branches.is_empty() {
         branches.push(branch);
     }
-    let branches = branches
-        .into_iter()
-        .map(|name| {
-            let refname = format!("refs/heads/{}", name);
-            Branch { name, refname }
-        })
-        .collect();
+    let branches = branches.into_iter().map(Branch::new).collect();
 
     let filters = cfg.filters.unwrap_or(vec![]);
     let commit_msg_filters = match regex::RegexSet::new(&filters) {
@@ -87,16 +246,49 @@ pub fn parse_args() -> Result<Options, error::Error> {
         }
     };
 
+    let commit_msg_blocks = message::build_block_filters(cfg.block_filters)?;
+    let commit_msg_rewrites = message::build_rewrites(cfg.rewrites)?;
+    let identities = identity::IdentityRewriter::new(cfg.identities)?;
+    let bootstrap_template = bootstrap::BootstrapTemplate::new(cfg.bootstrap)?;
+    let tree_filter = filter::TreeFilter::new(
+        cfg.include_paths,
+        cfg.exclude_paths,
+        cfg.path_mappings,
+        cfg.redactions,
+    )?;
+
     Ok(Options {
         repo: cfg.repo.unwrap_or(".".to_owned()),
         remote: cfg.remote,
         branches,
         commit_msg_filters,
+        commit_msg_blocks,
+        commit_msg_trailers: cfg.trailer_filters.unwrap_or_default(),
+        commit_msg_rewrites,
+        unknown_commit_ids: cfg
+            .unknown_commit_ids
+            .unwrap_or(message::UnknownCommitIds::Keep),
+        commit_id_regex: message::commit_id_regex(),
+        identities,
+        bootstrap_template,
+        tree_filter,
+        empty_commits: cfg.empty_commits.unwrap_or(filter::EmptyCommits::Drop),
+        secrets: secrets::SecretScanner::new(cfg.secrets)?,
+        mainlines: cfg.mainlines.unwrap_or_default(),
 
-        bootstrap: matches.is_present("bootstrap"),
+        command,
+        config_hash: config_hash.to_string(),
+        bootstrap: matches.is_present("bootstrap") || matches.is_present("bootstrap_at"),
+        bootstrap_at: matches.value_of("bootstrap_at").map(|s| s.to_owned()),
+        full_history: matches.is_present("full_history"),
         uproot: matches.is_present("uproot"),
-        verbose: !matches.is_present("quiet"),
+        verbose: !sub_matches.is_present("quiet"),
         yes: matches.is_present("yes"),
-        fetch: !matches.is_present("nofetch"),
+        dry_run: matches.is_present("dry_run"),
+        continue_sync: matches.is_present("continue"),
+        abort: matches.is_present("abort"),
+        worktree: matches.is_present("worktree"),
+        output,
+        fetch: !sub_matches.is_present("nofetch"),
     })
 }
@@ -0,0 +1,114 @@
+use crate::error::Error;
+use crate::identity::{IdentityCfg, IdentityRewriter};
+use crate::util;
+use serde::Deserialize;
+
+// {{{ Configuration
+
+#[derive(Deserialize)]
+pub struct BootstrapCfg {
+    author: Option<IdentityCfg>,
+    // if unset, the author is used
+    committer: Option<IdentityCfg>,
+    // date of the commits, with the format YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ, in UTC
+    date: Option<String>,
+    // summary of the commits, with the {remote}, {branch} and {commit} placeholders
+    message: Option<String>,
+}
+
+// }}}
+// {{{ Template
+
+const DEFAULT_MESSAGE: &str = "Bootstrap repository from remote {remote}";
+
+/// Identities, date and message of the bootstrap commits
+///
+/// Once configured, bootstrapping the same remote commit always creates the same commit,
+/// whoever runs ripit and whenever it runs.
+pub struct BootstrapTemplate {
+    author: Option<IdentityCfg>,
+    committer: Option<IdentityCfg>,
+    time: Option<git2::Time>,
+    message: String,
+}
+
+impl BootstrapTemplate {
+    pub fn new(cfg: Option<BootstrapCfg>) -> Result<Self, Error> {
+        let cfg = match cfg {
+            Some(cfg) => cfg,
+            None => {
+                return Ok(Self {
+                    author: None,
+                    committer: None,
+                    time: None,
+                    message: DEFAULT_MESSAGE.to_owned(),
+                })
+            }
+        };
+
+        let time = match cfg.date {
+            Some(date) => match util::parse_utc(&date) {
+                Some(timestamp) => Some(git2::Time::new(timestamp, 0)),
+                None => return Err(Error::InvalidBootstrapDate { date }),
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            author: cfg.author,
+            committer: cfg.committer,
+            time,
+            message: cfg.message.unwrap_or_else(|| DEFAULT_MESSAGE.to_owned()),
+        })
+    }
+
+    fn signature(&self, name: &str, email: &str) -> Result<git2::Signature<'static>, git2::Error> {
+        match &self.time {
+            Some(time) => git2::Signature::new(name, email, time),
+            None => git2::Signature::now(name, email),
+        }
+    }
+
+    /// Returns the author and the committer of a bootstrap commit
+    ///
+    /// If the author is not configured, the user running ripit is used, with the rewriting of
+    /// the identities of copied commits.
+    pub fn signatures(
+        &self,
+        repo: &git2::Repository,
+        identities: &IdentityRewriter,
+    ) -> Result<(git2::Signature<'static>, git2::Signature<'static>), git2::Error> {
+        let author = match &self.author {
+            Some(id) => self.signature(&id.name, &id.email)?,
+            None => {
+                let sig = identities.rewrite(&repo.signature()?)?;
+                match &self.time {
+                    Some(time) => git2::Signature::new(
+                        &String::from_utf8_lossy(sig.name_bytes()),
+                        &String::from_utf8_lossy(sig.email_bytes()),
+                        time,
+                    )?,
+                    None => sig,
+                }
+            }
+        };
+        let committer = match &self.committer {
+            Some(id) => self.signature(&id.name, &id.email)?,
+            None => author.clone(),
+        };
+        Ok((author, committer))
+    }
+
+    /// Returns the message of the bootstrap commit of a remote commit, with its ripit tag
+    pub fn message(&self, remote: &str, branch: &str, commit: git2::Oid) -> String {
+        let summary = self
+            .message
+            .replace("{remote}", remote)
+            .replace("{branch}", branch)
+            .replace("{commit}", &commit.to_string());
+
+        format!("{}\n\nrip-it: {}\n", summary.trim_end(), commit)
+    }
+}
+
+// }}}
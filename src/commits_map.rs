@@ -1,3 +1,4 @@
+use crate::app;
 use crate::error::Error;
 use crate::tag;
 use std::collections::hash_map::Entry;
@@ -13,17 +14,38 @@ pub struct SyncedCommit<'a> {
 
 type Map<'a> = HashMap<git2::Oid, SyncedCommit<'a>>;
 
+/// Mapping of the parents of a commit to synchronize
+pub struct ParentsMapping {
+    // whether the copy of the commit is uprooted
+    pub uprooted: bool,
+    // parents that are neither synchronized, nor synchronized before the commit
+    pub unknown: Vec<git2::Oid>,
+    // whether the commit can only be copied by uprooting it
+    pub uproot_needed: bool,
+}
+
+/// Returns true if the commit is a root commit, copied as a root commit as well
+///
+/// This is only the case when copying the whole history, otherwise root commits are uprooted.
+pub fn is_copied_as_root(commit: &git2::Commit, opts: &app::Options) -> bool {
+    opts.full_history && commit.parent_count() == 0
+}
+
 pub struct CommitsMap<'a> {
     // map of Oid in remote repo to Commit in local repo
     map: Map<'a>,
 
-    cache_file: std::fs::File,
+    // None in read-only mode, where the mappings are not saved
+    cache_file: Option<std::fs::File>,
 }
 
 impl<'a> CommitsMap<'a> {
-    pub fn new(repo: &'a git2::Repository) -> Result<Self, Error> {
-        // FIXME: reject bare repositories
-        let filename = repo.workdir().unwrap().join(".ripit-cache");
+    /// Build the map from the cache file and the HEAD of the repository
+    ///
+    /// If `read_only` is set, the new mappings are only kept in memory, and the cache file
+    /// is neither created nor modified.
+    pub fn new(repo: &'a git2::Repository, read_only: bool) -> Result<Self, Error> {
+        let filename = cache_path(repo);
         let mut map = Map::new();
 
         // fill map from cache file
@@ -36,11 +58,15 @@ impl<'a> CommitsMap<'a> {
         };
 
         // open cache file for writing
-        let mut opts = std::fs::OpenOptions::new();
-        opts.create(true).append(true);
-        let cache_file = match opts.open(&filename) {
-            Ok(f) => f,
-            Err(err) => return Err(Error::CacheOpenError { err, filename }),
+        let cache_file = if read_only {
+            None
+        } else {
+            let mut opts = std::fs::OpenOptions::new();
+            opts.create(true).append(true);
+            match opts.open(&filename) {
+                Ok(f) => Some(f),
+                Err(err) => return Err(Error::CacheOpenError { err, filename }),
+            }
         };
 
         let mut commits_map = Self { map, cache_file };
@@ -59,7 +85,7 @@ impl<'a> CommitsMap<'a> {
         branch: &str,
     ) -> Result<(), Error> {
         // fill map from synced branch.
-        let local_commit = repo.revparse_single(&branch)?.peel_to_commit()?;
+        let local_commit = repo.revparse_single(branch)?.peel_to_commit()?;
         self.fill_from_commit(repo, local_commit.id())
     }
 
@@ -93,28 +119,135 @@ impl<'a> CommitsMap<'a> {
         Ok(())
     }
 
+    /// Iterate over the remote commits, and the local commits they are mapped on
+    pub fn iter(&self) -> impl Iterator<Item = (&git2::Oid, &SyncedCommit<'a>)> {
+        self.map.iter()
+    }
+
     pub fn contains_key(&self, oid: git2::Oid) -> bool {
         self.map.contains_key(&oid)
     }
 
-    pub fn get(&self, oid: git2::Oid) -> Option<&SyncedCommit> {
+    pub fn get(&self, oid: git2::Oid) -> Option<&SyncedCommit<'_>> {
         self.map.get(&oid)
     }
 
+    /// Map the parents of a commit to synchronize on their copies
+    ///
+    /// `pending` contains the commits synchronized before it that are not in the map yet,
+    /// with whether they are uprooted. The copy of the commit is uprooted if every known
+    /// parent is, including when none is known, unless it is copied as a root commit. It can
+    /// only be copied by uprooting it if a parent is unknown, or if it is a root commit that
+    /// is not copied as such.
+    pub fn map_parents(
+        &self,
+        commit: &git2::Commit,
+        pending: &HashMap<git2::Oid, bool>,
+        opts: &app::Options,
+    ) -> ParentsMapping {
+        let copied_as_root = is_copied_as_root(commit, opts);
+        let mut mapping = ParentsMapping {
+            uprooted: !copied_as_root,
+            unknown: vec![],
+            uproot_needed: commit.parent_count() == 0 && !copied_as_root,
+        };
+
+        for parent_id in commit.parent_ids() {
+            let uprooted = match self.map.get(&parent_id) {
+                Some(parent) => Some(parent.uprooted),
+                None => pending.get(&parent_id).cloned(),
+            };
+            match uprooted {
+                // A commit with uprooted parents is uprooted
+                Some(uprooted) => mapping.uprooted &= uprooted,
+                None => {
+                    mapping.unknown.push(parent_id);
+                    mapping.uproot_needed = true;
+                }
+            }
+        }
+        mapping
+    }
+
+    /// Returns the local commit copied from the given remote commit
+    ///
+    /// Unlike `get`, None is returned for remote commits that were not copied, but mapped on
+    /// another local commit.
+    pub fn get_copy(&self, oid: git2::Oid) -> Option<&git2::Commit<'_>> {
+        let synced = self.map.get(&oid)?;
+        let (tag, _) = tag::retrieve_ripit_tag(&synced.commit)?;
+
+        if tag == oid.to_string() {
+            Some(&synced.commit)
+        } else {
+            None
+        }
+    }
+
     pub fn insert(&mut self, oid: git2::Oid, val: SyncedCommit<'a>) -> bool {
         match self.map.entry(oid) {
-            Entry::Occupied(_) => return false,
+            Entry::Occupied(_) => false,
             Entry::Vacant(v) => {
-                write_id_in_cache_file(&mut self.cache_file, val.commit.id());
+                if let Some(file) = &mut self.cache_file {
+                    write_id_in_cache_file(file, val.commit.id(), None);
+                }
                 v.insert(val);
                 true
             }
         }
     }
+
+    /// Map a remote commit on a local commit synchronized from another remote commit
+    ///
+    /// This is used for commits that were not copied, for example because all of their
+    /// changes were filtered out. As the mapping cannot be retrieved from the ripit tag of
+    /// the local commit, the remote commit id is saved in the cache file as well.
+    pub fn insert_alias(&mut self, oid: git2::Oid, val: SyncedCommit<'a>) -> bool {
+        match self.map.entry(oid) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(v) => {
+                if let Some(file) = &mut self.cache_file {
+                    write_id_in_cache_file(file, val.commit.id(), Some(oid));
+                }
+                v.insert(val);
+                true
+            }
+        }
+    }
+}
+
+/// Returns the path of the cache file
+///
+/// It is in the working directory, or in the git directory for bare repositories.
+pub fn cache_path(repo: &git2::Repository) -> PathBuf {
+    repo.workdir()
+        .unwrap_or_else(|| repo.path())
+        .join(".ripit-cache")
+}
+
+/// Truncate the cache file, removing the mappings saved after the first `len` bytes
+pub fn truncate_cache_file(repo: &git2::Repository, len: u64) -> Result<(), Error> {
+    let filename = cache_path(repo);
+    let res = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&filename)
+        .and_then(|f| f.set_len(len));
+
+    match res {
+        Ok(()) => Ok(()),
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(Error::CacheOpenError { err, filename }),
+        },
+    }
 }
 
-fn write_id_in_cache_file(file: &mut std::fs::File, id: git2::Oid) {
-    if let Err(err) = writeln!(file, "{}", id) {
+fn write_id_in_cache_file(file: &mut std::fs::File, id: git2::Oid, remote_id: Option<git2::Oid>) {
+    let res = match remote_id {
+        Some(remote_id) => writeln!(file, "{} {}", id, remote_id),
+        None => writeln!(file, "{}", id),
+    };
+    if let Err(err) = res {
         eprintln!("error when writing in cache file: {}", err);
     }
 }
@@ -126,10 +259,8 @@ fn fill_map_from_cache_file<'a>(
     filename: &PathBuf,
 ) -> Result<(), Error> {
     let reader = std::io::BufReader::new(&file);
-    let mut line_number = 0;
 
-    for line in reader.lines() {
-        line_number += 1;
+    for (line, line_number) in reader.lines().zip(1..) {
         let line = match line {
             Ok(line) => line,
             Err(err) => {
@@ -158,11 +289,16 @@ fn fill_map_from_cache_file<'a>(
     Ok(())
 }
 
+/// Parse a line of the cache file
+///
+/// The line contains the id of a local commit, optionally followed by the id of the remote
+/// commit it is mapped to. If not present, the remote commit is retrieved from the ripit tag.
 fn parse_cache_mapping<'a>(
     line: &str,
     repo: &'a git2::Repository,
 ) -> Result<(git2::Oid, SyncedCommit<'a>), String> {
-    let commit = match commit_from_mapping(line, repo) {
+    let mut parts = line.split_whitespace();
+    let commit = match commit_from_mapping(parts.next().unwrap_or(""), repo) {
         Ok(ci) => ci,
         Err(e) => return Err(e.message().to_owned()),
     };
@@ -170,6 +306,7 @@ fn parse_cache_mapping<'a>(
         Some(tag) => tag,
         None => return Err("Commit does not have a ripit tag".to_owned()),
     };
+    let tag = parts.next().map(|s| s.to_owned()).unwrap_or(tag);
 
     let remote_oid = match git2::Oid::from_str(&tag) {
         Ok(oid) => oid,
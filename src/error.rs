@@ -2,6 +2,7 @@ use std::fmt;
 use std::path::PathBuf;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     // generic git error
     Git(git2::Error),
@@ -14,9 +15,15 @@ pub enum Error {
         commit_id: git2::Oid,
         parent_id: git2::Oid,
     },
+    // a root commit to sync can only be synchronized by uprooting it
+    UnknownRoot {
+        commit_id: git2::Oid,
+    },
     // A synchronization caused conflicts in the index. The user has to solve them
     HasConflicts {
         summary: String,
+        // temporary worktree containing the conflicts, if not in the repository itself
+        worktree: Option<PathBuf>,
     },
 
     // error when opening the config file
@@ -34,6 +41,11 @@ pub enum Error {
         field: &'static str,
         error: regex::Error,
     },
+    // invalid glob pattern provided in the config
+    InvalidPathPattern {
+        field: &'static str,
+        error: glob::PatternError,
+    },
     // Cannot setup the merge context after conflicts
     CannotSetupMergeCtx,
     // I/O Error whe opening cache file
@@ -52,7 +64,112 @@ pub enum Error {
         filename: PathBuf,
         line: String,
         line_number: u32,
-    }
+    },
+    // I/O Error when reading the mailmap file
+    FailedOpenMailmap {
+        path: String,
+        error: std::io::Error,
+    },
+    // Invalid line in the mailmap file
+    InvalidMailmapLine {
+        desc: String,
+        path: String,
+        line: String,
+        line_number: u32,
+    },
+    // secrets were found in the commits to synchronize
+    SecretsFound {
+        nb_findings: usize,
+    },
+    // I/O Error when reading or writing the state file
+    StateFileError {
+        err: std::io::Error,
+        path: PathBuf,
+    },
+    // Invalid content in the state file
+    InvalidStateFile {
+        desc: String,
+        path: PathBuf,
+    },
+    // --continue was used, but no synchronization was stopped by conflicts
+    NoConflictToContinue,
+    // --continue was used, but the index still contains conflicts
+    UnresolvedConflicts,
+    // --abort was used, but no synchronization is in progress
+    NoSyncToAbort,
+    // no finished synchronization to roll back
+    NoSyncToRollback,
+    // a synchronization is in progress, it must be continued or aborted first
+    SyncInProgress,
+    // a branch was moved since the synchronization to roll back
+    BranchMovedSinceSync {
+        branch: String,
+    },
+    // I/O Error when reading or writing the journal
+    JournalError {
+        err: std::io::Error,
+        path: PathBuf,
+    },
+    // an entry of the journal cannot be parsed
+    InvalidJournalEntry {
+        error: serde_json::Error,
+        path: PathBuf,
+        line_number: usize,
+    },
+    // a date does not have the format YYYY-MM-DD
+    InvalidDate {
+        date: String,
+    },
+    // the date of the bootstrap commits in the configuration is invalid
+    InvalidBootstrapDate {
+        date: String,
+    },
+    // a revision given by the user cannot be resolved to a commit
+    UnknownRevision {
+        rev: String,
+    },
+    // a commit is neither a synchronized remote commit, nor a copy
+    CommitNotMapped {
+        commit_id: git2::Oid,
+    },
+    // a branch to synchronize outside of the working directory is checked out
+    BranchCheckedOut {
+        branch: String,
+    },
+    // the revision to bootstrap from is not in the remote branch
+    RevisionNotInBranch {
+        rev: String,
+        branch: String,
+    },
+    // no commit of the remote branch was made since the date to bootstrap from
+    NoCommitSince {
+        date: String,
+        branch: String,
+    },
+    // a branch to synchronize does not exist, and does not fork from a synchronized commit
+    BranchNotBootstrapped {
+        branch: String,
+    },
+    // the branch is not one of the branches to synchronize
+    UnknownBranch {
+        branch: String,
+    },
+    // an option of the synchronization is given with a command that does not synchronize
+    OptionNotSupported {
+        option: &'static str,
+        command: String,
+    },
+    // I/O Error when creating or removing a temporary worktree
+    WorktreeError {
+        err: std::io::Error,
+        path: PathBuf,
+    },
+    // the mainline configured for a merge commit is not one of its parents
+    InvalidMainline {
+        commit_id: git2::Oid,
+        parent: usize,
+        nb_parents: usize,
+    },
 }
 
 impl From<git2::Error> for Error {
@@ -61,6 +178,53 @@ impl From<git2::Error> for Error {
     }
 }
 
+impl Error {
+    /// Returns the name of the variant of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Git(_) => "Git",
+            Error::TagMissing => "TagMissing",
+            Error::HasLocalChanges => "HasLocalChanges",
+            Error::UnknownParent { .. } => "UnknownParent",
+            Error::UnknownRoot { .. } => "UnknownRoot",
+            Error::HasConflicts { .. } => "HasConflicts",
+            Error::FailedOpenCfg { .. } => "FailedOpenCfg",
+            Error::FailedParseCfg { .. } => "FailedParseCfg",
+            Error::InvalidConfig { .. } => "InvalidConfig",
+            Error::InvalidPathPattern { .. } => "InvalidPathPattern",
+            Error::CannotSetupMergeCtx => "CannotSetupMergeCtx",
+            Error::CacheOpenError { .. } => "CacheOpenError",
+            Error::CacheReadError { .. } => "CacheReadError",
+            Error::CacheInvalidLine { .. } => "CacheInvalidLine",
+            Error::FailedOpenMailmap { .. } => "FailedOpenMailmap",
+            Error::InvalidMailmapLine { .. } => "InvalidMailmapLine",
+            Error::SecretsFound { .. } => "SecretsFound",
+            Error::StateFileError { .. } => "StateFileError",
+            Error::InvalidStateFile { .. } => "InvalidStateFile",
+            Error::NoConflictToContinue => "NoConflictToContinue",
+            Error::UnresolvedConflicts => "UnresolvedConflicts",
+            Error::NoSyncToAbort => "NoSyncToAbort",
+            Error::NoSyncToRollback => "NoSyncToRollback",
+            Error::SyncInProgress => "SyncInProgress",
+            Error::BranchMovedSinceSync { .. } => "BranchMovedSinceSync",
+            Error::BranchCheckedOut { .. } => "BranchCheckedOut",
+            Error::BranchNotBootstrapped { .. } => "BranchNotBootstrapped",
+            Error::UnknownBranch { .. } => "UnknownBranch",
+            Error::OptionNotSupported { .. } => "OptionNotSupported",
+            Error::RevisionNotInBranch { .. } => "RevisionNotInBranch",
+            Error::NoCommitSince { .. } => "NoCommitSince",
+            Error::UnknownRevision { .. } => "UnknownRevision",
+            Error::CommitNotMapped { .. } => "CommitNotMapped",
+            Error::JournalError { .. } => "JournalError",
+            Error::InvalidJournalEntry { .. } => "InvalidJournalEntry",
+            Error::InvalidDate { .. } => "InvalidDate",
+            Error::InvalidBootstrapDate { .. } => "InvalidBootstrapDate",
+            Error::WorktreeError { .. } => "WorktreeError",
+            Error::InvalidMainline { .. } => "InvalidMainline",
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -83,13 +247,29 @@ impl fmt::Display for Error {
                  local repository",
                 commit_id, parent_id
             ),
-            Error::HasConflicts { summary } => write!(
+            Error::UnknownRoot { commit_id } => write!(
                 f,
-                "Cannot synchronize the following commit due to conflicts:\n  {}\n\
-                 Solve the conflicts and commit the resolutions, \
-                 then run the synchronization again.",
-                summary
+                "Cannot synchronize commit {}: it is a root commit, it can only be \
+                 synchronized by uprooting it",
+                commit_id
             ),
+            Error::HasConflicts { summary, worktree } => {
+                writeln!(
+                    f,
+                    "Cannot synchronize the following commit due to conflicts:\n  {}",
+                    summary
+                )?;
+                if let Some(path) = worktree {
+                    writeln!(f, "The conflicts are in the worktree {}.", path.display())?;
+                }
+                write!(
+                    f,
+                    "Solve the conflicts and add the resolutions to the index, \
+                     then run the synchronization with `--continue`.\n\
+                     To restore the repository as it was before the synchronization, \
+                     run it with `--abort`."
+                )
+            }
             Error::FailedOpenCfg { path, error } => {
                 write!(f, "Cannot open configuration file {}: {}", path, error)
             }
@@ -99,6 +279,9 @@ impl fmt::Display for Error {
             Error::InvalidConfig { field, error } => {
                 write!(f, "Invalid {} option: {}", field, error)
             }
+            Error::InvalidPathPattern { field, error } => {
+                write!(f, "Invalid {} option: {}", field, error)
+            }
             Error::CannotSetupMergeCtx => write!(
                 f,
                 "Cannot setup the environment for the resolution of conflicts.\n\
@@ -114,7 +297,118 @@ impl fmt::Display for Error {
             Error::CacheInvalidLine { desc, filename, line, line_number } => {
                 write!(f, "{}:{}: line \"{}\" is invalid: {}", filename.display(), line_number,
                 line, desc)
+            },
+            Error::FailedOpenMailmap { path, error } => {
+                write!(f, "Cannot read mailmap file {}: {}", path, error)
+            }
+            Error::InvalidMailmapLine { desc, path, line, line_number } => {
+                write!(f, "{}:{}: line \"{}\" is invalid: {}", path, line_number, line, desc)
+            }
+            Error::SecretsFound { nb_findings } => write!(
+                f,
+                "{} potential secrets found in the commits to synchronize.\n\
+                 Remove them, or allow the commits in the configuration, \
+                 then run the synchronization again.",
+                nb_findings
+            ),
+            Error::StateFileError { err, path } => {
+                write!(f, "Cannot access state file {}: {}", path.display(), err)
+            }
+            Error::InvalidStateFile { desc, path } => {
+                write!(f, "Invalid state file {}: {}", path.display(), desc)
+            }
+            Error::NoConflictToContinue => {
+                write!(f, "No synchronization stopped by conflicts to continue.")
+            }
+            Error::UnresolvedConflicts => write!(
+                f,
+                "The index still contains conflicts.\n\
+                 Solve them and add the resolutions to the index, then continue \
+                 the synchronization."
+            ),
+            Error::NoSyncToAbort => write!(f, "No synchronization in progress to abort."),
+            Error::NoSyncToRollback => write!(f, "No synchronization to roll back."),
+            Error::SyncInProgress => write!(
+                f,
+                "A synchronization is in progress, use --continue or --abort first."
+            ),
+            Error::BranchMovedSinceSync { branch } => write!(
+                f,
+                "Branch {} was modified since the last synchronization, it cannot be rolled \
+                 back without losing its new commits.",
+                branch
+            ),
+            Error::JournalError { err, path } => {
+                write!(f, "Cannot access journal {}: {}", path.display(), err)
+            }
+            Error::InvalidJournalEntry {
+                error,
+                path,
+                line_number,
+            } => write!(
+                f,
+                "{}:{}: invalid journal entry: {}",
+                path.display(),
+                line_number,
+                error
+            ),
+            Error::InvalidDate { date } => {
+                write!(f, "Invalid date \"{}\", expected YYYY-MM-DD.", date)
             }
+            Error::InvalidBootstrapDate { date } => write!(
+                f,
+                "Invalid bootstrap date \"{}\", expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ.",
+                date
+            ),
+            Error::UnknownRevision { rev } => {
+                write!(f, "Revision {} cannot be found in the repository.", rev)
+            }
+            Error::CommitNotMapped { commit_id } => write!(
+                f,
+                "Commit {} is neither a synchronized private commit, nor a public copy.",
+                commit_id
+            ),
+            Error::BranchCheckedOut { branch } => write!(
+                f,
+                "Branch {} is checked out, it cannot be synchronized in a worktree.\nAborted.",
+                branch
+            ),
+            Error::RevisionNotInBranch { rev, branch } => write!(
+                f,
+                "Revision {} is not in branch {}, it cannot be bootstrapped from it.",
+                rev, branch
+            ),
+            Error::NoCommitSince { date, branch } => {
+                write!(f, "No commit was made on branch {} since {}.", branch, date)
+            }
+            Error::BranchNotBootstrapped { branch } => write!(
+                f,
+                "Branch {} does not fork from a synchronized commit, it must be bootstrapped \
+                 with --bootstrap.",
+                branch
+            ),
+            Error::UnknownBranch { branch } => write!(
+                f,
+                "Branch {} is not in the branches to synchronize of the configuration.",
+                branch
+            ),
+            Error::OptionNotSupported { option, command } => write!(
+                f,
+                "Option {} cannot be used with the {} command.",
+                option, command
+            ),
+            Error::WorktreeError { err, path } => {
+                write!(f, "Cannot setup worktree {}: {}", path.display(), err)
+            }
+            Error::InvalidMainline {
+                commit_id,
+                parent,
+                nb_parents,
+            } => write!(
+                f,
+                "Invalid mainline {} configured for commit {}: it has {} parents",
+                parent, commit_id, nb_parents
+            ),
         }
     }
 }
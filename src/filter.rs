@@ -0,0 +1,356 @@
+use crate::error::Error;
+use crate::redact::{BlobRedactor, RedactionCfg};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Behavior for commits whose changes are entirely filtered out
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyCommits {
+    // copy the commit nonetheless, without any changes
+    Keep,
+    // do not copy the commit
+    Drop,
+}
+
+/// Move of a directory (or file) of the repository to another path
+#[derive(Deserialize)]
+pub struct PathMapping {
+    from: String,
+    to: String,
+}
+
+const TREE_FILEMODE: i32 = 0o040000;
+const BLOB_FILEMODE: i32 = 0o100644;
+const BLOB_EXECUTABLE_FILEMODE: i32 = 0o100755;
+
+/// Filter applied on the trees of copied commits
+///
+/// Paths are matched against glob patterns, relative to the root of the repository.
+/// A path is kept if it, or one of its parent directories, matches one of the include
+/// patterns (or if there are no include patterns), and if neither it nor its parent
+/// directories match one of the exclude patterns.
+///
+/// The private regions of the files kept are redacted, then the path mappings are applied
+/// in order on the tree.
+pub struct TreeFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    mappings: Vec<(Vec<String>, Vec<String>)>,
+    redactor: BlobRedactor,
+
+    // cache of filtered trees, indexed by path and id of the original tree
+    cache: RefCell<HashMap<(String, git2::Oid), Option<git2::Oid>>>,
+    // cache of the results of filter_tree, indexed by the id of the original tree
+    roots_cache: RefCell<HashMap<git2::Oid, git2::Oid>>,
+}
+
+fn build_patterns(
+    patterns: Option<Vec<String>>,
+    field: &'static str,
+) -> Result<Vec<glob::Pattern>, Error> {
+    let mut res = Vec::new();
+
+    for pattern in patterns.unwrap_or_default() {
+        // "dir/" is allowed, and matches the directory
+        match glob::Pattern::new(pattern.trim_end_matches('/')) {
+            Ok(p) => res.push(p),
+            Err(error) => return Err(Error::InvalidPathPattern { field, error }),
+        }
+    }
+    Ok(res)
+}
+
+/// Split a path in its components, ignoring leading and trailing slashes
+fn split_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_owned())
+        .collect()
+}
+
+fn matches_any(patterns: &[glob::Pattern], path: &str) -> bool {
+    let opts = glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    patterns.iter().any(|p| p.matches_with(path, opts))
+}
+
+impl TreeFilter {
+    pub fn new(
+        include: Option<Vec<String>>,
+        exclude: Option<Vec<String>>,
+        mappings: Option<Vec<PathMapping>>,
+        redactions: Option<Vec<RedactionCfg>>,
+    ) -> Result<Self, Error> {
+        let mappings = mappings
+            .unwrap_or_default()
+            .iter()
+            .map(|m| (split_path(&m.from), split_path(&m.to)))
+            .collect();
+
+        Ok(Self {
+            include: build_patterns(include, "include_paths")?,
+            exclude: build_patterns(exclude, "exclude_paths")?,
+            mappings,
+            redactor: BlobRedactor::new(redactions)?,
+            cache: RefCell::new(HashMap::new()),
+            roots_cache: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns true if the filter does not modify any tree
+    pub fn is_noop(&self) -> bool {
+        self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.mappings.is_empty()
+            && self.redactor.is_noop()
+    }
+
+    /// Build a new tree, only containing the entries that are not filtered out, with the
+    /// private regions of files redacted, and with the path mappings applied.
+    pub fn filter_tree<'a>(
+        &self,
+        repo: &'a git2::Repository,
+        tree: &git2::Tree,
+    ) -> Result<git2::Tree<'a>, git2::Error> {
+        if self.is_noop() {
+            return repo.find_tree(tree.id());
+        }
+        if let Some(oid) = self.roots_cache.borrow().get(&tree.id()) {
+            return repo.find_tree(*oid);
+        }
+
+        let mut res = self.filter_subtree(repo, tree, "", self.include.is_empty())?;
+        for (from, to) in &self.mappings {
+            res = map_path(repo, res, from, to)?;
+        }
+
+        let oid = match res {
+            Some(oid) => oid,
+            // everything was filtered, use an empty tree
+            None => repo.treebuilder(None)?.write()?,
+        };
+        self.roots_cache.borrow_mut().insert(tree.id(), oid);
+        repo.find_tree(oid)
+    }
+
+    /// Filter a subtree located at the given path
+    ///
+    /// `included` indicates whether one of the parent directories matched an include pattern.
+    /// None is returned if the filtered subtree is empty.
+    fn filter_subtree(
+        &self,
+        repo: &git2::Repository,
+        tree: &git2::Tree,
+        path: &str,
+        included: bool,
+    ) -> Result<Option<git2::Oid>, git2::Error> {
+        let key = (path.to_owned(), tree.id());
+        if let Some(res) = self.cache.borrow().get(&key) {
+            return Ok(*res);
+        }
+
+        let mut builder = repo.treebuilder(None)?;
+        for entry in tree.iter() {
+            let name = entry.name_bytes();
+            let entry_path = if path.is_empty() {
+                String::from_utf8_lossy(name).into_owned()
+            } else {
+                format!("{}/{}", path, String::from_utf8_lossy(name))
+            };
+
+            if matches_any(&self.exclude, &entry_path) {
+                continue;
+            }
+            let entry_included = included || matches_any(&self.include, &entry_path);
+
+            if entry.kind() == Some(git2::ObjectType::Tree) {
+                let subtree = repo.find_tree(entry.id())?;
+                if let Some(oid) =
+                    self.filter_subtree(repo, &subtree, &entry_path, entry_included)?
+                {
+                    builder.insert(name, oid, entry.filemode())?;
+                }
+            } else if entry_included {
+                let oid = match entry.filemode() {
+                    BLOB_FILEMODE | BLOB_EXECUTABLE_FILEMODE => {
+                        self.redactor.redact_blob(repo, entry.id())?
+                    }
+                    // symbolic links and submodules are never redacted
+                    _ => entry.id(),
+                };
+                builder.insert(name, oid, entry.filemode())?;
+            }
+        }
+
+        let res = if builder.is_empty() {
+            None
+        } else {
+            Some(builder.write()?)
+        };
+        self.cache.borrow_mut().insert(key, res);
+        Ok(res)
+    }
+}
+
+// {{{ Path mappings
+
+// id and filemode of a tree entry
+type Entry = (git2::Oid, i32);
+
+fn find_subtree<'a>(
+    repo: &'a git2::Repository,
+    tree: Option<git2::Oid>,
+) -> Result<Option<git2::Tree<'a>>, git2::Error> {
+    match tree {
+        Some(oid) => Ok(Some(repo.find_tree(oid)?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the id of the tree entry named `name`, if it is a tree
+fn subtree_id(tree: Option<&git2::Tree>, name: &str) -> Option<git2::Oid> {
+    let entry = tree?.get_name(name)?;
+    if entry.kind() == Some(git2::ObjectType::Tree) {
+        Some(entry.id())
+    } else {
+        None
+    }
+}
+
+fn write_builder(builder: git2::TreeBuilder) -> Result<Option<git2::Oid>, git2::Error> {
+    if builder.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(builder.write()?))
+    }
+}
+
+/// Merge the entries of the tree `over` in the tree `base`
+///
+/// Entries present in both trees are taken from `over`, unless both are trees, in which
+/// case they are merged recursively.
+fn overlay_trees(
+    repo: &git2::Repository,
+    base: Option<git2::Oid>,
+    over: git2::Oid,
+) -> Result<Option<git2::Oid>, git2::Error> {
+    let base = match find_subtree(repo, base)? {
+        Some(base) => base,
+        None => return Ok(Some(over)),
+    };
+    let over = repo.find_tree(over)?;
+
+    let mut builder = repo.treebuilder(Some(&base))?;
+    for entry in over.iter() {
+        let name = entry.name_bytes();
+        let base_entry = base
+            .get_name(&String::from_utf8_lossy(name))
+            .map(|e| (e.id(), e.kind()));
+
+        match base_entry {
+            Some((base_id, Some(git2::ObjectType::Tree)))
+                if entry.kind() == Some(git2::ObjectType::Tree) =>
+            {
+                if let Some(oid) = overlay_trees(repo, Some(base_id), entry.id())? {
+                    builder.insert(name, oid, TREE_FILEMODE)?;
+                }
+            }
+            _ => {
+                builder.insert(name, entry.id(), entry.filemode())?;
+            }
+        }
+    }
+    write_builder(builder)
+}
+
+/// Remove the entry at the given path in the tree
+///
+/// The removed entry is returned, alongside the new tree.
+fn remove_path(
+    repo: &git2::Repository,
+    tree: Option<git2::Oid>,
+    path: &[String],
+) -> Result<(Option<git2::Oid>, Option<Entry>), git2::Error> {
+    let tree = match find_subtree(repo, tree)? {
+        Some(tree) => tree,
+        None => return Ok((None, None)),
+    };
+    let entry = match tree.get_name(&path[0]) {
+        Some(entry) => (entry.id(), entry.filemode()),
+        None => return Ok((Some(tree.id()), None)),
+    };
+
+    let mut builder = repo.treebuilder(Some(&tree))?;
+    let removed = if path.len() == 1 {
+        builder.remove(&path[0])?;
+        Some(entry)
+    } else if entry.1 == TREE_FILEMODE {
+        let (subtree, removed) = remove_path(repo, Some(entry.0), &path[1..])?;
+        match subtree {
+            Some(oid) => {
+                builder.insert(&path[0], oid, TREE_FILEMODE)?;
+            }
+            None => builder.remove(&path[0])?,
+        };
+        removed
+    } else {
+        None
+    };
+    Ok((write_builder(builder)?, removed))
+}
+
+/// Insert an entry at the given path in the tree
+///
+/// If a tree is inserted where a tree already exists, both are merged.
+fn insert_path(
+    repo: &git2::Repository,
+    tree: Option<git2::Oid>,
+    path: &[String],
+    entry: Entry,
+) -> Result<Option<git2::Oid>, git2::Error> {
+    if path.is_empty() {
+        // only trees can be inserted at the root
+        return overlay_trees(repo, tree, entry.0);
+    }
+
+    let tree = find_subtree(repo, tree)?;
+    let mut builder = repo.treebuilder(tree.as_ref())?;
+    let existing = subtree_id(tree.as_ref(), &path[0]);
+
+    if path.len() == 1 && entry.1 != TREE_FILEMODE {
+        builder.insert(&path[0], entry.0, entry.1)?;
+    } else if let Some(oid) = insert_path(repo, existing, &path[1..], entry)? {
+        builder.insert(&path[0], oid, TREE_FILEMODE)?;
+    }
+    write_builder(builder)
+}
+
+/// Move the entry at path `from` to the path `to`
+fn map_path(
+    repo: &git2::Repository,
+    tree: Option<git2::Oid>,
+    from: &[String],
+    to: &[String],
+) -> Result<Option<git2::Oid>, git2::Error> {
+    if from.is_empty() {
+        // moving the root in a subdirectory
+        return match tree {
+            Some(oid) => insert_path(repo, None, to, (oid, TREE_FILEMODE)),
+            None => Ok(None),
+        };
+    }
+
+    match remove_path(repo, tree, from)? {
+        // a file cannot become the root, ignore the mapping
+        (_, Some(entry)) if to.is_empty() && entry.1 != TREE_FILEMODE => Ok(tree),
+        (new_tree, Some(entry)) => insert_path(repo, new_tree, to, entry),
+        (_, None) => Ok(tree),
+    }
+}
+
+// }}}
@@ -0,0 +1,257 @@
+use crate::error::Error;
+use serde::Deserialize;
+use std::io::BufRead;
+
+// {{{ Configuration
+
+#[derive(Deserialize)]
+pub struct IdentityCfg {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct RuleCfg {
+    // exact match on the name or email of the identity
+    name: Option<String>,
+    email: Option<String>,
+    // regex match on the name or email of the identity
+    name_regex: Option<String>,
+    email_regex: Option<String>,
+
+    // replacements. If unset, the matched value is kept
+    new_name: Option<String>,
+    new_email: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct IdentitiesCfg {
+    mailmap: Option<String>,
+    rules: Option<Vec<RuleCfg>>,
+    anonymous: Option<IdentityCfg>,
+}
+
+// }}}
+// {{{ Rules
+
+enum Matcher {
+    Exact(String),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Matcher::Exact(s) => s == value,
+            Matcher::Regex(r) => r.is_match(value),
+        }
+    }
+}
+
+struct Rule {
+    name: Option<Matcher>,
+    email: Option<Matcher>,
+    new_name: Option<String>,
+    new_email: Option<String>,
+}
+
+impl Rule {
+    fn is_match(&self, name: &str, email: &str) -> bool {
+        self.name.iter().all(|m| m.is_match(name)) && self.email.iter().all(|m| m.is_match(email))
+    }
+}
+
+fn build_matcher(
+    exact: Option<String>,
+    regex: Option<String>,
+    field: &'static str,
+) -> Result<Option<Matcher>, Error> {
+    if let Some(s) = exact {
+        return Ok(Some(Matcher::Exact(s)));
+    }
+    match regex {
+        Some(r) => match regex::Regex::new(&r) {
+            Ok(r) => Ok(Some(Matcher::Regex(r))),
+            Err(error) => Err(Error::InvalidConfig { field, error }),
+        },
+        None => Ok(None),
+    }
+}
+
+// }}}
+// {{{ Mailmap
+
+/// Parse a line of a mailmap file, following the format described in git-mailmap(5)
+///
+/// The email matched is compared case-insensitively, as git does.
+fn parse_mailmap_line(line: &str) -> Result<Option<Rule>, String> {
+    let line = match line.find('#') {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    // split the line in (name, email) pairs
+    let mut pairs = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(pos) => start + pos,
+            None => return Err("unterminated email".to_owned()),
+        };
+        let name = rest[..start].trim();
+        let name = if name.is_empty() {
+            None
+        } else {
+            Some(name.to_owned())
+        };
+        pairs.push((name, rest[(start + 1)..end].to_owned()));
+        rest = &rest[(end + 1)..];
+    }
+    if !rest.trim().is_empty() {
+        return Err("trailing characters after the last email".to_owned());
+    }
+
+    let (proper, commit) = match pairs.len() {
+        1 => {
+            let (name, email) = pairs.remove(0);
+            ((name, None), (None, email))
+        }
+        2 => {
+            let commit = pairs.remove(1);
+            let (name, email) = pairs.remove(0);
+            ((name, Some(email)), commit)
+        }
+        _ => return Err("expected one or two emails".to_owned()),
+    };
+
+    let email_regex = format!("(?i)^{}$", regex::escape(&commit.1));
+    Ok(Some(Rule {
+        name: commit.0.map(Matcher::Exact),
+        // the regex is escaped, it cannot fail to compile
+        email: Some(Matcher::Regex(regex::Regex::new(&email_regex).unwrap())),
+        new_name: proper.0,
+        new_email: proper.1,
+    }))
+}
+
+fn parse_mailmap(path: &str) -> Result<Vec<Rule>, Error> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(error) => {
+            return Err(Error::FailedOpenMailmap {
+                path: path.to_owned(),
+                error,
+            })
+        }
+    };
+
+    let mut rules = Vec::new();
+    let reader = std::io::BufReader::new(file);
+    for (line, line_number) in reader.lines().zip(1..) {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                return Err(Error::FailedOpenMailmap {
+                    path: path.to_owned(),
+                    error,
+                })
+            }
+        };
+
+        match parse_mailmap_line(&line) {
+            Ok(Some(rule)) => rules.push(rule),
+            Ok(None) => (),
+            Err(desc) => {
+                return Err(Error::InvalidMailmapLine {
+                    desc,
+                    path: path.to_owned(),
+                    line,
+                    line_number,
+                })
+            }
+        }
+    }
+
+    Ok(rules)
+}
+
+// }}}
+// {{{ Rewriter
+
+/// Rewrite identities of commits, to redact the authors and committers of copied commits
+///
+/// Rules from the configuration are tried first, in order, then the rules from the
+/// mailmap file. Identities not matched by any rule are replaced by the anonymous
+/// identity if set, or kept as is.
+pub struct IdentityRewriter {
+    rules: Vec<Rule>,
+    anonymous: Option<(String, String)>,
+}
+
+impl IdentityRewriter {
+    pub fn new(cfg: Option<IdentitiesCfg>) -> Result<Self, Error> {
+        let cfg = match cfg {
+            Some(cfg) => cfg,
+            None => {
+                return Ok(Self {
+                    rules: vec![],
+                    anonymous: None,
+                })
+            }
+        };
+
+        let mut rules = Vec::new();
+        for rule in cfg.rules.unwrap_or_default() {
+            rules.push(Rule {
+                name: build_matcher(rule.name, rule.name_regex, "identities name_regex")?,
+                email: build_matcher(rule.email, rule.email_regex, "identities email_regex")?,
+                new_name: rule.new_name,
+                new_email: rule.new_email,
+            });
+        }
+        if let Some(path) = cfg.mailmap {
+            rules.append(&mut parse_mailmap(&path)?);
+        }
+
+        Ok(Self {
+            rules,
+            anonymous: cfg.anonymous.map(|id| (id.name, id.email)),
+        })
+    }
+
+    /// Returns the rewritten (name, email) pair of an identity
+    fn rewrite_identity(&self, name: &str, email: &str) -> (String, String) {
+        for rule in &self.rules {
+            if rule.is_match(name, email) {
+                return (
+                    rule.new_name.clone().unwrap_or_else(|| name.to_owned()),
+                    rule.new_email.clone().unwrap_or_else(|| email.to_owned()),
+                );
+            }
+        }
+
+        match &self.anonymous {
+            Some((name, email)) => (name.clone(), email.clone()),
+            None => (name.to_owned(), email.to_owned()),
+        }
+    }
+
+    /// Rewrite a signature, keeping its timestamp
+    pub fn rewrite(&self, sig: &git2::Signature) -> Result<git2::Signature<'static>, git2::Error> {
+        let name = String::from_utf8_lossy(sig.name_bytes());
+        let email = String::from_utf8_lossy(sig.email_bytes());
+        let (name, email) = self.rewrite_identity(&name, &email);
+
+        git2::Signature::new(&name, &email, &sig.when())
+    }
+
+    /// Returns true if no identity can be modified by this rewriter
+    pub fn is_noop(&self) -> bool {
+        self.rules.is_empty() && self.anonymous.is_none()
+    }
+}
+
+// }}}
@@ -0,0 +1,223 @@
+use crate::app;
+use crate::error::Error;
+use crate::report::{self, BranchResult};
+use crate::util;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+
+// {{{ Entry
+
+/// Record of an execution of ripit, appended as a JSON line in .git/ripit/journal
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    // bootstrap, sync, continue, abort or rollback
+    action: String,
+    // user who ran ripit, from the git configuration
+    user: String,
+    // hash of the configuration file
+    config_hash: String,
+    started: String,
+    finished: String,
+    // commits created, dropped and left to synchronize, for every branch
+    branches: Vec<BranchResult>,
+    // error that stopped the execution, if any
+    error: Option<String>,
+}
+
+impl Entry {
+    fn print(&self, opts: &app::Options) {
+        info!(
+            opts,
+            "{} by {} at {}, with configuration {}",
+            self.action,
+            self.user,
+            self.started,
+            self.config_hash
+        );
+        for result in &self.branches {
+            info!(
+                opts,
+                "  Branch {}: {} created, {} dropped, {} pending",
+                result.branch,
+                result.created.len(),
+                result.dropped.len(),
+                result.pending.len()
+            );
+            for created in &result.created {
+                info!(opts, "    {} -> {}", created.id, created.public_id);
+            }
+            for id in &result.dropped {
+                info!(opts, "    {} dropped", id);
+            }
+            for id in &result.pending {
+                info!(opts, "    {} pending", id);
+            }
+        }
+        if let Some(error) = &self.error {
+            info!(opts, "  Error: {}", error);
+        }
+        info!(opts);
+    }
+}
+
+// }}}
+// {{{ Journal
+
+fn journal_path(repo: &git2::Repository) -> PathBuf {
+    repo.path().join("ripit").join("journal")
+}
+
+/// Entry of the current execution, written once it is finished
+pub struct Journal {
+    entry: Entry,
+}
+
+impl Journal {
+    pub fn new(repo: &git2::Repository, opts: &app::Options) -> Self {
+        let action = if opts.command == app::Command::Rollback {
+            "rollback"
+        } else if let app::Command::Rebootstrap(_) = opts.command {
+            "rebootstrap"
+        } else if opts.abort {
+            "abort"
+        } else if opts.continue_sync {
+            "continue"
+        } else if opts.bootstrap {
+            "bootstrap"
+        } else {
+            "sync"
+        };
+        let user = match repo.signature() {
+            Ok(sig) => sig.to_string(),
+            Err(_) => std::env::var("USER").unwrap_or_default(),
+        };
+
+        Self {
+            entry: Entry {
+                action: action.to_owned(),
+                user,
+                config_hash: opts.config_hash.clone(),
+                started: util::format_utc(std::time::SystemTime::now()),
+                finished: String::new(),
+                branches: vec![],
+                error: None,
+            },
+        }
+    }
+
+    /// Record the outcome of the synchronization or the bootstrap of a branch
+    pub fn record(&mut self, result: BranchResult) {
+        self.entry.branches.push(result);
+    }
+
+    /// Append the entry in the journal, with the result of the execution
+    pub fn write(mut self, repo: &git2::Repository, res: &Result<(), Error>) -> Result<(), Error> {
+        self.entry.finished = util::format_utc(std::time::SystemTime::now());
+        if let Err(err) = res {
+            self.entry.error = Some(err.to_string());
+        }
+
+        let path = journal_path(repo);
+        let line = report::to_json(&self.entry);
+        let res = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+            })
+            .and_then(|mut file| writeln!(file, "{}", line));
+        match res {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::JournalError { err, path }),
+        }
+    }
+}
+
+// }}}
+// {{{ Log
+
+/// Criteria to select entries of the journal
+#[derive(Default, PartialEq)]
+pub struct LogFilter {
+    // dates with the format YYYY-MM-DD, both included
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub branch: Option<String>,
+    // id of a remote commit, or a prefix of it
+    pub commit: Option<String>,
+}
+
+impl LogFilter {
+    fn matches(&self, entry: &Entry) -> bool {
+        let date = entry.started.get(..10).unwrap_or("");
+        if let Some(since) = &self.since {
+            if date < since.as_str() {
+                return false;
+            }
+        }
+        if let Some(until) = &self.until {
+            if date > until.as_str() {
+                return false;
+            }
+        }
+        if let Some(branch) = &self.branch {
+            if !entry.branches.iter().any(|r| &r.branch == branch) {
+                return false;
+            }
+        }
+        if let Some(commit) = &self.commit {
+            let is_commit = |id: &String| id.starts_with(commit.as_str());
+            if !entry.branches.iter().any(|r| {
+                r.created.iter().any(|c| is_commit(&c.id))
+                    || r.dropped.iter().any(is_commit)
+                    || r.pending.iter().any(is_commit)
+            }) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Print the entries of the journal matching the filter
+pub fn print_log(
+    repo: &git2::Repository,
+    filter: &LogFilter,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let path = journal_path(repo);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => String::new(),
+            _ => return Err(Error::JournalError { err, path }),
+        },
+    };
+
+    for (line_number, line) in content.lines().enumerate() {
+        let entry: Entry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                return Err(Error::InvalidJournalEntry {
+                    error,
+                    path,
+                    line_number: line_number + 1,
+                })
+            }
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+
+        if opts.output == app::Output::Json {
+            println!("{}", line);
+        } else {
+            entry.print(opts);
+        }
+    }
+    Ok(())
+}
+
+// }}}
@@ -1,9 +1,24 @@
+// first, so that its macros are available to the other modules
+#[macro_use]
+mod util;
+
 mod app;
+mod bootstrap;
 mod commits_map;
 mod error;
+mod filter;
+mod identity;
+mod journal;
+mod mapping;
+mod message;
+mod redact;
+mod report;
+mod secrets;
+mod state;
+mod status;
 mod sync;
 mod tag;
-mod util;
+mod worktree;
 
 /// Check that the local repo does not contain any staged or unstaged changes
 ///
@@ -29,37 +44,167 @@ fn check_local_diff(repo: &git2::Repository) -> Result<(), error::Error> {
     }
 }
 
-fn _main() -> Result<(), error::Error> {
-    let opts = app::parse_args()?;
+/// Move HEAD back where an interrupted synchronization started
+///
+/// The copies are committed on a detached HEAD without updating the working directory, which
+/// thus still matches the commit HEAD pointed to when the synchronization was interrupted.
+fn reset_interrupted_head(repo: &git2::Repository) -> Result<(), error::Error> {
+    if let Some(state) = state::SyncState::load(repo)? {
+        if state.conflict.is_none() && repo.head_detached()? {
+            state.reset_head(repo)?;
+        }
+    }
+    Ok(())
+}
 
-    let repo = git2::Repository::open(&opts.repo)?;
-    check_local_diff(&repo)?;
+/// Check that none of the branches to synchronize is checked out
+///
+/// When the working directory is not used, only the branches are moved: the working directory
+/// would no longer match the checked out branch.
+fn check_head_not_synced(repo: &git2::Repository, opts: &app::Options) -> Result<(), error::Error> {
+    let head = repo.find_reference("HEAD")?;
+    if let Some(refname) = head.symbolic_target() {
+        if let Some(branch) = opts.branches.iter().find(|b| b.refname == refname) {
+            return Err(error::Error::BranchCheckedOut {
+                branch: branch.name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Finish the synchronization, or roll it back if it failed
+///
+/// A synchronization stopped by conflicts is kept, so that it can be continued once they are
+/// solved, as is a synchronization that failed after being continued, so that the resolution
+/// of the conflicts is not lost, and a copy of the whole history of new branches, so that it
+/// can be resumed.
+fn end_sync(
+    repo: &git2::Repository,
+    res: &Result<bool, error::Error>,
+    opts: &app::Options,
+) -> Result<(), error::Error> {
+    match res {
+        Ok(_) => {
+            if let Some(state) = state::SyncState::load(repo)? {
+                sync::apply_staged_branches(repo, &state, opts)?;
+                // put the user back where the synchronization started
+                if sync::uses_workdir(repo, opts) {
+                    state.restore_head(repo)?;
+                }
+            }
+            state::SyncState::finish(repo)
+        }
+        Err(error::Error::HasConflicts { .. }) => Ok(()),
+        Err(_) if opts.continue_sync => Ok(()),
+        Err(_) => {
+            if let Some(mut state) = state::SyncState::load(repo)? {
+                if state.is_copying_history(repo, opts) {
+                    return Ok(());
+                }
+                state.restore(repo)?;
+            }
+            state::SyncState::end(repo)
+        }
+    }
+}
+
+fn run(
+    repo: &git2::Repository,
+    opts: &app::Options,
+    journal: &mut journal::Journal,
+) -> Result<(), error::Error> {
+    if opts.abort {
+        return sync::abort_sync(repo, opts);
+    }
+    // the resolution of the conflicts is expected in the index when continuing
+    if !sync::uses_workdir(repo, opts) {
+        if !repo.is_bare() {
+            check_head_not_synced(repo, opts)?;
+        }
+    } else if !opts.continue_sync && check_local_diff(repo).is_err() {
+        reset_interrupted_head(repo)?;
+        check_local_diff(repo)?;
+    }
+
+    if opts.command == app::Command::Rollback {
+        return sync::rollback_sync(repo, opts);
+    }
 
     if opts.fetch {
         // fetch last commits in remote
-        sync::update_remote(&repo, &opts)?;
+        sync::update_remote(repo, opts)?;
     }
 
-    let mut commits_map = commits_map::CommitsMap::new(&repo)?;
+    if !opts.dry_run {
+        // the resolution of conflicts committed by the user must be redacted before being
+        // mapped
+        sync::redact_committed_resolution(repo, opts)?;
+    }
+
+    let mut commits_map = commits_map::CommitsMap::new(repo, opts.dry_run)?;
+    for branch in &opts.branches {
+        // new branches are created from the synchronized commits they fork from
+        if repo.find_reference(&branch.refname).is_ok() {
+            commits_map.fill_from_branch(repo, &branch.name)?;
+        }
+        // copies staged by an unfinished synchronization
+        let staging_refname = branch.staging_refname();
+        if repo.find_reference(&staging_refname).is_ok() {
+            commits_map.fill_from_branch(repo, &staging_refname)?;
+        }
+    }
+
+    if let app::Command::Rebootstrap(name) = &opts.command {
+        if state::SyncState::load(repo)?.is_some() {
+            return Err(error::Error::SyncInProgress);
+        }
+        let branch = match opts.branches.iter().find(|b| &b.name == name) {
+            Some(branch) => branch,
+            None => {
+                return Err(error::Error::UnknownBranch {
+                    branch: name.clone(),
+                })
+            }
+        };
+
+        // saved as a synchronization, so that the new commit can be rolled back
+        state::SyncState::begin(repo, opts, sync::uses_workdir(repo, opts))?;
+        let res =
+            sync::rebootstrap_branch(repo, branch, &mut commits_map, journal, opts).map(|_| true);
+        end_sync(repo, &res, opts)?;
+        return res.map(|_| ());
+    }
 
     if opts.bootstrap {
         for branch in &opts.branches {
             // bootstrap the branch in the local repo with the state of the
             // branch in the remote repo
-            sync::bootstrap_branch_with_remote(&repo, branch, &mut commits_map, &opts)?
+            sync::bootstrap_branch_with_remote(repo, branch, &mut commits_map, journal, opts)?
         }
     } else {
-        let mut has_synced = false;
-
-        for branch in &opts.branches {
-            commits_map.fill_from_branch(&repo, &branch.name)?;
+        if opts.continue_sync {
+            sync::continue_sync(repo, &mut commits_map, opts)?;
+        } else if !opts.dry_run {
+            sync::pick_up_committed_resolution(repo, &mut commits_map, opts)?;
+        }
+        if !opts.dry_run {
+            // save the positions of the branches, so that the synchronization can be aborted
+            state::SyncState::begin(repo, opts, sync::uses_workdir(repo, opts))?;
         }
 
-        for branch in &opts.branches {
-            // sync local branch with remote by cherry-picking missing commits
-            has_synced |= sync::sync_branch_with_remote(&repo, branch, &mut commits_map, &opts)?
+        // sync local branches with remote by cherry-picking missing commits. The copies are
+        // staged, and the branches only moved once every branch is synchronized.
+        let res = opts.branches.iter().try_fold(false, |has_synced, branch| {
+            let synced =
+                sync::sync_branch_with_remote(repo, branch, &mut commits_map, journal, opts)?;
+            Ok(has_synced || synced)
+        });
+        if !opts.dry_run {
+            end_sync(repo, &res, opts)?;
         }
-        if !opts.fetch && !has_synced {
+        let has_synced = res?;
+        if !opts.fetch && !has_synced && !opts.continue_sync {
             eprintln!(
                 "No commits to synchronize found. Have you fetched \
                  the latest commits from the private repository with \
@@ -71,6 +216,49 @@ fn _main() -> Result<(), error::Error> {
     Ok(())
 }
 
+fn execute(opts: &mut app::Options) -> Result<(), error::Error> {
+    let repo = git2::Repository::open(&opts.repo)?;
+    // an interrupted copy of whole histories is resumed as such, and a synchronization
+    // outside of the working directory is continued outside of it
+    if let Some(state) = state::SyncState::load(&repo)? {
+        opts.full_history |= state.full_history;
+        opts.worktree |= !state.uses_workdir;
+    }
+    let opts = &*opts;
+
+    match &opts.command {
+        app::Command::Log(filter) => return journal::print_log(&repo, filter, opts),
+        app::Command::Map(rev) => return mapping::print_mapping(&repo, rev, opts),
+        app::Command::Status => return status::print_status(&repo, opts),
+        _ => (),
+    }
+
+    // record every execution modifying the repository in the journal
+    let mut journal = journal::Journal::new(&repo, opts);
+    let res = run(&repo, opts, &mut journal);
+    if opts.dry_run {
+        return res;
+    }
+    let written = journal.write(&repo, &res);
+    res.and(written)
+}
+
+fn _main() -> Result<(), error::Error> {
+    let (output, opts) = app::parse_args();
+
+    let res = opts.and_then(|mut opts| execute(&mut opts));
+    if let Err(error) = &res {
+        // wherever the error was raised, including in the configuration, so that failures are
+        // always reported as events
+        if output == app::Output::Json {
+            report::print_json(&report::Event::Error {
+                error: &report::ErrorReport::new(error, None),
+            });
+        }
+    }
+    res
+}
+
 fn main() {
     std::process::exit(match _main() {
         Ok(_) => 0,
@@ -0,0 +1,130 @@
+use crate::app;
+use crate::commits_map::CommitsMap;
+use crate::error::Error;
+use crate::report;
+use crate::tag;
+use serde::Serialize;
+
+/// Counterparts of a commit in the remote and the local repositories
+#[derive(Serialize)]
+struct Mapping {
+    // commit of the remote repository
+    private: String,
+    // commit of the local repository it is mapped on
+    public: String,
+    uprooted: bool,
+    // whether the remote commit was dropped, and mapped on the copy of one of its parents
+    dropped: bool,
+    // other remote commits dropped and mapped on the local commit
+    aliases: Vec<String>,
+    // synchronized branches containing the local commit
+    branches: Vec<String>,
+}
+
+/// Find the mapping of a remote commit, or of a local commit
+fn find_mapping(commit: &git2::Commit, commits_map: &CommitsMap) -> Result<Mapping, Error> {
+    let (private, public, uprooted, dropped) = match commits_map.get(commit.id()) {
+        Some(synced) => (
+            commit.id(),
+            synced.commit.id(),
+            synced.uprooted,
+            commits_map.get_copy(commit.id()).is_none(),
+        ),
+        None => match tag::retrieve_ripit_tag(commit) {
+            Some((sha, uprooted)) => (git2::Oid::from_str(&sha)?, commit.id(), uprooted, false),
+            None => {
+                return Err(Error::CommitNotMapped {
+                    commit_id: commit.id(),
+                })
+            }
+        },
+    };
+
+    let mut aliases: Vec<String> = commits_map
+        .iter()
+        .filter(|(oid, synced)| {
+            synced.commit.id() == public
+                && **oid != private
+                && commits_map.get_copy(**oid).is_none()
+        })
+        .map(|(oid, _)| oid.to_string())
+        .collect();
+    aliases.sort();
+
+    Ok(Mapping {
+        private: private.to_string(),
+        public: public.to_string(),
+        uprooted,
+        dropped,
+        aliases,
+        branches: vec![],
+    })
+}
+
+/// Print the counterpart of a commit of the remote or the local repository
+///
+/// The commit can be given by any revision understood by git: an abbreviated id, a reference,
+/// ...
+pub fn print_mapping(repo: &git2::Repository, rev: &str, opts: &app::Options) -> Result<(), Error> {
+    let commit = match repo
+        .revparse_single(rev)
+        .and_then(|obj| obj.peel_to_commit())
+    {
+        Ok(commit) => commit,
+        Err(_) => {
+            return Err(Error::UnknownRevision {
+                rev: rev.to_owned(),
+            })
+        }
+    };
+
+    let mut commits_map = CommitsMap::new(repo, true)?;
+    for branch in &opts.branches {
+        // the branch may not be bootstrapped yet
+        if repo.find_reference(&branch.refname).is_ok() {
+            commits_map.fill_from_branch(repo, &branch.name)?;
+        }
+        // copies staged by an unfinished synchronization
+        let staging_refname = branch.staging_refname();
+        if repo.find_reference(&staging_refname).is_ok() {
+            commits_map.fill_from_branch(repo, &staging_refname)?;
+        }
+    }
+    let mut mapping = find_mapping(&commit, &commits_map)?;
+
+    let public = git2::Oid::from_str(&mapping.public)?;
+    for branch in &opts.branches {
+        // the branch may not be bootstrapped yet
+        if let Ok(tip) = repo.refname_to_id(&branch.refname) {
+            if tip == public || repo.graph_descendant_of(tip, public)? {
+                mapping.branches.push(branch.name.clone());
+            }
+        }
+    }
+
+    if opts.output == app::Output::Json {
+        report::print_json(&mapping);
+        return Ok(());
+    }
+
+    info!(opts, "Private commit {}", mapping.private);
+    if mapping.dropped {
+        info!(
+            opts,
+            "Dropped, all its changes are filtered out. Mapped on public commit {}", mapping.public
+        );
+    } else if mapping.uprooted {
+        info!(opts, "Public commit {} (uprooted)", mapping.public);
+    } else {
+        info!(opts, "Public commit {}", mapping.public);
+    }
+    for alias in &mapping.aliases {
+        info!(opts, "Also mapped from dropped private commit {}", alias);
+    }
+    if mapping.branches.is_empty() {
+        info!(opts, "Not in any synchronized branch");
+    } else {
+        info!(opts, "In branches: {}", mapping.branches.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use crate::app;
+use crate::commits_map::CommitsMap;
+use crate::error::Error;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct RewriteCfg {
+    pattern: String,
+    replacement: String,
+}
+
+/// Substitution applied on the messages of copied commits
+pub struct Rewrite {
+    regex: regex::Regex,
+    // replacement, that can reference capture groups of the regex
+    replacement: String,
+}
+
+/// Behavior for ids of commits mentioned in messages, that were not copied
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownCommitIds {
+    // leave the id untouched
+    Keep,
+    // replace the id by REDACTED_COMMIT_ID
+    Redact,
+}
+
+const REDACTED_COMMIT_ID: &str = "<redacted>";
+
+#[derive(Deserialize)]
+pub struct BlockFilterCfg {
+    begin: String,
+    end: String,
+}
+
+/// Filter removing blocks of lines from the messages of copied commits
+///
+/// The block starts with the line matching `begin`, and ends with the line matching `end`.
+/// Both lines are removed as well.
+pub struct BlockFilter {
+    begin: regex::Regex,
+    end: regex::Regex,
+}
+
+pub fn build_block_filters(cfg: Option<Vec<BlockFilterCfg>>) -> Result<Vec<BlockFilter>, Error> {
+    let build_regex = |pattern: &str| match regex::Regex::new(pattern) {
+        Ok(regex) => Ok(regex),
+        Err(error) => Err(Error::InvalidConfig {
+            field: "block_filters",
+            error,
+        }),
+    };
+
+    let mut filters = Vec::new();
+    for block in cfg.unwrap_or_default() {
+        filters.push(BlockFilter {
+            begin: build_regex(&block.begin)?,
+            end: build_regex(&block.end)?,
+        });
+    }
+    Ok(filters)
+}
+
+pub fn build_rewrites(cfg: Option<Vec<RewriteCfg>>) -> Result<Vec<Rewrite>, Error> {
+    let mut rewrites = Vec::new();
+
+    for rewrite in cfg.unwrap_or_default() {
+        match regex::Regex::new(&rewrite.pattern) {
+            Ok(regex) => rewrites.push(Rewrite {
+                regex,
+                replacement: rewrite.replacement,
+            }),
+            Err(error) => {
+                return Err(Error::InvalidConfig {
+                    field: "rewrites",
+                    error,
+                })
+            }
+        }
+    }
+    Ok(rewrites)
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Join the lines, removing consecutive blank lines, and blank lines at the start and end of
+/// the message.
+fn join_without_blank_lines(lines: &[&str]) -> String {
+    let mut res: Vec<&str> = Vec::new();
+
+    for line in lines {
+        if is_blank(line) && res.last().map_or(true, |l| is_blank(l)) {
+            continue;
+        }
+        res.push(line);
+    }
+    while res.last().map_or(false, |l| is_blank(l)) {
+        res.pop();
+    }
+
+    res.join("\n")
+}
+
+fn filter_blocks(msg: &str, opts: &app::Options) -> String {
+    if opts.commit_msg_blocks.is_empty() {
+        return msg.to_owned();
+    }
+
+    let mut new_lines = Vec::new();
+    let mut block_end: Option<&regex::Regex> = None;
+    let mut filtered = false;
+    for line in msg.lines() {
+        let in_block = match block_end {
+            Some(end) => {
+                if end.is_match(line) {
+                    block_end = None;
+                }
+                true
+            }
+            None => match opts
+                .commit_msg_blocks
+                .iter()
+                .find(|b| b.begin.is_match(line))
+            {
+                Some(block) => {
+                    block_end = Some(&block.end);
+                    true
+                }
+                None => false,
+            },
+        };
+
+        if in_block {
+            if opts.verbose {
+                info!(opts, "  Filtering out line '{}'", line);
+            }
+            filtered = true;
+        } else {
+            new_lines.push(line);
+        }
+    }
+
+    if filtered {
+        join_without_blank_lines(&new_lines)
+    } else {
+        msg.to_owned()
+    }
+}
+
+/// Returns the key of the trailer, if the line is a trailer
+fn trailer_key(line: &str) -> Option<&str> {
+    let key = &line[..line.find(':')?];
+
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+/// Remove the trailers whose keys are filtered
+///
+/// The trailers are the lines of the last paragraph of the message, if this paragraph only
+/// contains lines formatted as "key: value", or continuation lines starting with whitespaces.
+fn filter_trailers(msg: &str, opts: &app::Options) -> String {
+    if opts.commit_msg_trailers.is_empty() {
+        return msg.to_owned();
+    }
+
+    let lines: Vec<&str> = msg.lines().collect();
+    let end = match lines.iter().rposition(|l| !is_blank(l)) {
+        Some(pos) => pos + 1,
+        None => return msg.to_owned(),
+    };
+    let start = match lines[..end].iter().rposition(|l| is_blank(l)) {
+        Some(pos) => pos + 1,
+        // the summary is never a trailer
+        None => return msg.to_owned(),
+    };
+    let paragraph = &lines[start..end];
+    let is_trailer_line = |l: &&str| trailer_key(l).is_some() || l.starts_with(char::is_whitespace);
+    if trailer_key(paragraph[0]).is_none() || !paragraph.iter().all(is_trailer_line) {
+        return msg.to_owned();
+    }
+
+    let mut new_lines = lines[..start].to_vec();
+    let mut removed = false;
+    let mut filtered = false;
+    for line in paragraph {
+        // continuation lines are removed alongside their trailer
+        if let Some(key) = trailer_key(line) {
+            removed = opts
+                .commit_msg_trailers
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(key));
+        }
+
+        if removed {
+            if opts.verbose {
+                info!(opts, "  Filtering out line '{}'", line);
+            }
+            filtered = true;
+        } else {
+            new_lines.push(line);
+        }
+    }
+
+    if filtered {
+        join_without_blank_lines(&new_lines)
+    } else {
+        msg.to_owned()
+    }
+}
+
+fn filter_commit_msg(msg: &str, opts: &app::Options) -> String {
+    if opts.commit_msg_filters.len() == 0 {
+        return msg.to_owned();
+    }
+
+    let new_lines: Vec<&str> = msg
+        .lines()
+        .filter(|line| {
+            if opts.commit_msg_filters.is_match(line) {
+                if opts.verbose {
+                    info!(opts, "  Filtering out line '{}'", line);
+                }
+                false
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    new_lines.join("\n")
+}
+
+/// Regex matching the ids of commits, full or abbreviated, mentioned in messages
+pub fn commit_id_regex() -> regex::Regex {
+    // the regex is hardcoded, it cannot fail to compile
+    regex::Regex::new(r"\b[0-9a-fA-F]{7,40}\b").unwrap()
+}
+
+// Resolution of an id mentioned in a message
+enum MentionedId {
+    // id of a copied commit, translated to the id of the copy
+    Copied(String),
+    // id of a commit that was not copied, and is only in the private history
+    Private,
+    // not the id of a single commit, or the id of a commit of the public history
+    Other,
+}
+
+/// Returns true if the commit is in the history of one of the synchronized branches
+fn is_public(repo: &git2::Repository, oid: git2::Oid, opts: &app::Options) -> bool {
+    opts.branches.iter().any(|branch| {
+        [&branch.refname, &branch.staging_refname()]
+            .iter()
+            .filter_map(|refname| repo.refname_to_id(refname).ok())
+            .any(|tip| tip == oid || repo.graph_descendant_of(tip, oid).unwrap_or(false))
+    })
+}
+
+/// Resolve an id mentioned in a message, full or abbreviated
+fn resolve_mentioned_id(
+    id: &str,
+    repo: &git2::Repository,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> MentionedId {
+    let oid = match repo.revparse_single(id) {
+        // revparse also resolves references, make sure the id was used
+        Ok(obj) if obj.id().to_string().starts_with(&id.to_lowercase()) => {
+            if obj.kind() != Some(git2::ObjectType::Commit) {
+                return MentionedId::Other;
+            }
+            obj.id()
+        }
+        // including ids that are a prefix of several objects, which cannot be resolved
+        _ => return MentionedId::Other,
+    };
+
+    match commits_map.get_copy(oid) {
+        // keep the length of the original id
+        Some(commit) => MentionedId::Copied(commit.id().to_string()[..id.len()].to_owned()),
+        None if is_public(repo, oid, opts) => MentionedId::Other,
+        None => MentionedId::Private,
+    }
+}
+
+/// Replace the ids of commits mentioned in the message by the ids of their copies
+///
+/// Ids of commits that were not copied, and are not in the public history, are kept or
+/// redacted, depending on the configuration.
+fn translate_commit_ids(
+    msg: String,
+    repo: &git2::Repository,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> String {
+    opts.commit_id_regex
+        .replace_all(&msg, |caps: &regex::Captures| {
+            let id = &caps[0];
+            match resolve_mentioned_id(id, repo, commits_map, opts) {
+                MentionedId::Copied(public_id) => public_id,
+                MentionedId::Private if opts.unknown_commit_ids == UnknownCommitIds::Redact => {
+                    if opts.verbose {
+                        info!(opts, "  Redacting commit id '{}'", id);
+                    }
+                    REDACTED_COMMIT_ID.to_owned()
+                }
+                MentionedId::Private | MentionedId::Other => id.to_owned(),
+            }
+        })
+        .into_owned()
+}
+
+/// Apply the substitutions on the message, in order
+fn rewrite_commit_msg(msg: String, opts: &app::Options) -> String {
+    opts.commit_msg_rewrites.iter().fold(msg, |msg, rewrite| {
+        rewrite
+            .regex
+            .replace_all(&msg, rewrite.replacement.as_str())
+            .into_owned()
+    })
+}
+
+// TODO: use a string builder, to avoid the double alloc
+pub fn update_commit_msg(
+    orig_msg: &str,
+    tag: &str,
+    repo: &git2::Repository,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> String {
+    let orig_msg = filter_blocks(orig_msg, opts);
+    let orig_msg = filter_trailers(&orig_msg, opts);
+    let orig_msg = filter_commit_msg(&orig_msg, opts);
+    let orig_msg = translate_commit_ids(orig_msg, repo, commits_map, opts);
+    let orig_msg = rewrite_commit_msg(orig_msg, opts);
+    if orig_msg.ends_with('\n') {
+        format!("{}\n{}\n", orig_msg, tag)
+    } else {
+        format!("{}\n\n{}\n", orig_msg, tag)
+    }
+}
@@ -0,0 +1,171 @@
+use crate::error::Error;
+use regex::bytes::Regex;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+pub struct RedactionCfg {
+    begin: String,
+    end: String,
+    placeholder: Option<String>,
+}
+
+/// Private region of files, delimited by lines matching the begin and end markers
+struct Redaction {
+    begin: Regex,
+    end: Regex,
+    // line replacing the region. If unset, the region is removed
+    placeholder: Option<Vec<u8>>,
+}
+
+/// Redaction of the private regions of the files of copied commits
+///
+/// Every line between a line matching the `begin` marker of a redaction and the next line
+/// matching its `end` marker is removed, markers included. If a placeholder is set, it
+/// replaces the region, with the indentation of the `begin` marker. A region that is never
+/// closed extends to the end of the file.
+///
+/// Binary files are never redacted.
+pub struct BlobRedactor {
+    redactions: Vec<Redaction>,
+
+    // cache of redacted blobs, indexed by the id of the original blob
+    cache: RefCell<HashMap<git2::Oid, git2::Oid>>,
+}
+
+/// Returns true if the content is binary, using the same heuristic as git
+fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|c| *c == 0)
+}
+
+/// Split the content in lines, keeping their terminators
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, c) in content.iter().enumerate() {
+        if *c == b'\n' {
+            lines.push(&content[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < content.len() {
+        lines.push(&content[start..]);
+    }
+    lines
+}
+
+/// Returns the length of the line without its terminator, LF or CRLF
+fn line_body_len(line: &[u8]) -> usize {
+    let mut len = line.len();
+    if line[..len].ends_with(b"\n") {
+        len -= 1;
+    }
+    if line[..len].ends_with(b"\r") {
+        len -= 1;
+    }
+    len
+}
+
+impl BlobRedactor {
+    pub fn new(cfg: Option<Vec<RedactionCfg>>) -> Result<Self, Error> {
+        let build_regex = |pattern: &str| match Regex::new(pattern) {
+            Ok(regex) => Ok(regex),
+            Err(error) => Err(Error::InvalidConfig {
+                field: "redactions",
+                error,
+            }),
+        };
+
+        let mut redactions = Vec::new();
+        for redaction in cfg.unwrap_or_default() {
+            redactions.push(Redaction {
+                begin: build_regex(&redaction.begin)?,
+                end: build_regex(&redaction.end)?,
+                placeholder: redaction.placeholder.map(|p| p.into_bytes()),
+            });
+        }
+
+        Ok(Self {
+            redactions,
+            cache: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns true if no blob can be modified by this redactor
+    pub fn is_noop(&self) -> bool {
+        self.redactions.is_empty()
+    }
+
+    /// Returns the id of the redacted blob
+    ///
+    /// If the blob does not contain any private region, its id is returned.
+    pub fn redact_blob(
+        &self,
+        repo: &git2::Repository,
+        oid: git2::Oid,
+    ) -> Result<git2::Oid, git2::Error> {
+        if self.is_noop() {
+            return Ok(oid);
+        }
+        if let Some(res) = self.cache.borrow().get(&oid) {
+            return Ok(*res);
+        }
+
+        let blob = repo.find_blob(oid)?;
+        let res = match self.redact(blob.content()) {
+            Some(content) => repo.blob(&content)?,
+            None => oid,
+        };
+        self.cache.borrow_mut().insert(oid, res);
+        Ok(res)
+    }
+
+    /// Redact the content, returning None if it is left unchanged
+    fn redact(&self, content: &[u8]) -> Option<Vec<u8>> {
+        if is_binary(content) {
+            return None;
+        }
+
+        let mut res = Vec::with_capacity(content.len());
+        let mut region_end: Option<&Regex> = None;
+        let mut redacted = false;
+        // the line endings are kept, so that they are preserved
+        for line in split_lines(content) {
+            if let Some(end) = region_end {
+                if end.is_match(line) {
+                    region_end = None;
+                }
+                continue;
+            }
+
+            match self.redactions.iter().find(|r| r.begin.is_match(line)) {
+                Some(redaction) => {
+                    // the end marker can be on the same line
+                    let after_begin = redaction.begin.find(line).map_or(0, |m| m.end());
+                    if !redaction.end.is_match(&line[after_begin..]) {
+                        region_end = Some(&redaction.end);
+                    }
+                    if let Some(placeholder) = &redaction.placeholder {
+                        let indent = line
+                            .iter()
+                            .take_while(|c| **c == b' ' || **c == b'\t')
+                            .count();
+                        // keep the line terminator of the begin marker, LF or CRLF
+                        res.extend_from_slice(&line[..indent]);
+                        res.extend_from_slice(placeholder);
+                        res.extend_from_slice(&line[line_body_len(line)..]);
+                    }
+                    redacted = true;
+                }
+                None => res.extend_from_slice(line),
+            }
+        }
+
+        if redacted {
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
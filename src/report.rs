@@ -0,0 +1,254 @@
+use crate::app;
+use crate::commits_map::{CommitsMap, SyncedCommit};
+use crate::error::Error;
+use crate::journal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// {{{ JSON
+
+/// Serialize the value as a single line of JSON
+pub fn to_json<T: Serialize>(value: &T) -> String {
+    // the values serialized by ripit only contain strings, numbers and booleans, they cannot
+    // fail to serialize
+    serde_json::to_string(value).unwrap()
+}
+
+/// Print the value on stdout, as a single line of JSON
+pub fn print_json<T: Serialize>(value: &T) {
+    println!("{}", to_json(value));
+}
+
+// }}}
+// {{{ Events
+
+#[derive(Serialize)]
+pub struct Identity {
+    name: String,
+    email: String,
+}
+
+impl Identity {
+    fn new(sig: &git2::Signature) -> Self {
+        Self {
+            name: String::from_utf8_lossy(sig.name_bytes()).into_owned(),
+            email: String::from_utf8_lossy(sig.email_bytes()).into_owned(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PlannedParent {
+    // id of the parent in the remote repository
+    id: String,
+    // id of its copy in the local repository, unset if the parent is to be synchronized
+    // as well, or if it is unknown
+    public_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PlannedCommit {
+    id: String,
+    author: Identity,
+    // author of the copy, once the identities are rewritten
+    public_author: Identity,
+    summary: String,
+    parents: Vec<PlannedParent>,
+    uprooted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreatedCommit {
+    pub id: String,
+    pub public_id: String,
+    pub uprooted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ErrorReport {
+    // name of the variant of the error
+    pub kind: String,
+    pub message: String,
+    // id of the remote commit being synchronized when the error occurred
+    pub commit: Option<String>,
+}
+
+impl ErrorReport {
+    pub fn new(error: &Error, commit: Option<&git2::Commit>) -> Self {
+        Self {
+            kind: error.kind().to_owned(),
+            message: error.to_string(),
+            commit: commit.map(|c| c.id().to_string()),
+        }
+    }
+}
+
+/// Outcome of the synchronization or the bootstrap of a branch
+#[derive(Serialize, Deserialize)]
+pub struct BranchResult {
+    pub branch: String,
+    pub created: Vec<CreatedCommit>,
+    // commits not copied, as all of their changes were filtered out
+    pub dropped: Vec<String>,
+    // commits not synchronized, because the synchronization was stopped
+    pub pending: Vec<String>,
+    pub error: Option<ErrorReport>,
+}
+
+impl BranchResult {
+    pub fn bootstrap(branch: &app::Branch, commit: git2::Oid, public_id: git2::Oid) -> Self {
+        Self {
+            branch: branch.name.clone(),
+            created: vec![CreatedCommit {
+                id: commit.to_string(),
+                public_id: public_id.to_string(),
+                uprooted: false,
+            }],
+            dropped: vec![],
+            pending: vec![],
+            error: None,
+        }
+    }
+}
+
+/// Event printed on stdout with the JSON output, as a single line
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event<'a> {
+    // commits about to be synchronized on a branch
+    Plan {
+        branch: &'a str,
+        commits: Vec<PlannedCommit>,
+    },
+    // outcome of the synchronization of a branch
+    Result {
+        dry_run: bool,
+        #[serde(flatten)]
+        result: &'a BranchResult,
+    },
+    // bootstrap of a branch
+    Bootstrap {
+        branch: &'a str,
+        commit: String,
+        public_id: String,
+    },
+    // error that made the execution fail
+    Error {
+        #[serde(flatten)]
+        error: &'a ErrorReport,
+    },
+}
+
+/// Print the event on stdout, if the JSON output is used
+pub fn emit(event: &Event, opts: &app::Options) {
+    if opts.output == app::Output::Json {
+        print_json(event);
+    }
+}
+
+// }}}
+// {{{ Plan
+
+/// Build the plan of the synchronization of the commits
+///
+/// The commits must be ordered so that parents are listed before their children.
+pub fn plan_commits(
+    commits: &[git2::Commit],
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> Result<Vec<PlannedCommit>, git2::Error> {
+    // uprooted status of the commits to synchronize
+    let mut pending_uprooted = HashMap::new();
+    let mut plan = Vec::new();
+
+    for commit in commits {
+        let uprooted = commits_map
+            .map_parents(commit, &pending_uprooted, opts)
+            .uprooted;
+        let parents = commit
+            .parent_ids()
+            .map(|parent_id| PlannedParent {
+                id: parent_id.to_string(),
+                public_id: commits_map
+                    .get(parent_id)
+                    .map(|parent| parent.commit.id().to_string()),
+            })
+            .collect();
+        pending_uprooted.insert(commit.id(), uprooted);
+
+        plan.push(PlannedCommit {
+            id: commit.id().to_string(),
+            author: Identity::new(&commit.author()),
+            public_author: Identity::new(&opts.identities.rewrite(&commit.author())?),
+            summary: commit.summary().unwrap_or("").to_owned(),
+            parents,
+            uprooted,
+        });
+    }
+
+    Ok(plan)
+}
+
+// }}}
+// {{{ Result
+
+/// Outcome of the synchronization of a branch
+#[derive(Default)]
+pub struct SyncReport {
+    created: Vec<CreatedCommit>,
+    dropped: Vec<String>,
+    error: Option<ErrorReport>,
+}
+
+impl SyncReport {
+    pub fn created(&mut self, commit: &git2::Commit, copy: &SyncedCommit) {
+        self.created.push(CreatedCommit {
+            id: commit.id().to_string(),
+            public_id: copy.commit.id().to_string(),
+            uprooted: copy.uprooted,
+        });
+    }
+
+    pub fn dropped(&mut self, commit: &git2::Commit) {
+        self.dropped.push(commit.id().to_string());
+    }
+
+    /// Returns the number of commits synchronized
+    pub fn nb_processed(&self) -> usize {
+        self.created.len() + self.dropped.len()
+    }
+
+    /// Record the error that stopped the synchronization, and the commit being synchronized
+    pub fn failed(&mut self, error: &Error, commit: Option<&git2::Commit>) {
+        self.error = Some(ErrorReport::new(error, commit));
+    }
+
+    /// Emit the result of the synchronization, and record it in the journal
+    ///
+    /// `pending` are the commits that were not synchronized.
+    pub fn emit(
+        self,
+        branch: &app::Branch,
+        pending: &[git2::Commit],
+        journal: &mut journal::Journal,
+        opts: &app::Options,
+    ) {
+        let result = BranchResult {
+            branch: branch.name.clone(),
+            created: self.created,
+            dropped: self.dropped,
+            pending: pending.iter().map(|c| c.id().to_string()).collect(),
+            error: self.error,
+        };
+        emit(
+            &Event::Result {
+                dry_run: opts.dry_run,
+                result: &result,
+            },
+            opts,
+        );
+        journal.record(result);
+    }
+}
+
+// }}}
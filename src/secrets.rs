@@ -0,0 +1,248 @@
+use crate::app;
+use crate::error::Error;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+const DEFAULT_ENTROPY_MIN_LENGTH: usize = 20;
+
+#[derive(Deserialize)]
+pub struct SecretsCfg {
+    // regexes matching secrets
+    patterns: Option<Vec<String>>,
+    // minimum entropy, in bits per character, of strings considered as secrets
+    entropy_threshold: Option<f64>,
+    // minimum length of strings checked for entropy
+    entropy_min_length: Option<usize>,
+    // ids of remote commits that are not scanned
+    allowed_commits: Option<Vec<String>>,
+}
+
+/// Scanner of the changes of commits, looking for secrets about to be published
+///
+/// Every line added by a commit is matched against the configured patterns. If an entropy
+/// threshold is configured, the line is also split in words, and words with an entropy
+/// above the threshold are reported as well.
+pub struct SecretScanner {
+    patterns: Vec<regex::Regex>,
+    entropy: Option<(f64, regex::Regex)>,
+    allowed_commits: Vec<String>,
+}
+
+// line added by a commit
+struct AddedLine {
+    path: String,
+    line_number: u32,
+    content: String,
+}
+
+/// Returns the Shannon entropy of the string, in bits per character
+fn shannon_entropy(s: &str) -> f64 {
+    let mut counts = std::collections::HashMap::new();
+    for c in s.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+
+    let len = s.chars().count() as f64;
+    counts
+        .values()
+        .map(|count| {
+            let p = f64::from(*count) / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Mask a secret, so that it can be listed without being leaked
+///
+/// Only its first and last two characters are kept, and only if it is long enough for them
+/// to reveal little of it.
+fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 12 {
+        return "*".repeat(chars.len());
+    }
+    let first: String = chars[..2].iter().collect();
+    let last: String = chars[chars.len() - 2..].iter().collect();
+    format!("{}{}{}", first, "*".repeat(chars.len() - 4), last)
+}
+
+/// Returns the lines added in the diff between the two trees
+fn added_lines(
+    repo: &git2::Repository,
+    old_tree: Option<&git2::Tree>,
+    new_tree: &git2::Tree,
+) -> Result<Vec<AddedLine>, git2::Error> {
+    let diff = repo.diff_tree_to_tree(old_tree, Some(new_tree), None)?;
+    let mut lines = Vec::new();
+
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        None,
+        Some(&mut |delta, _, line| {
+            if line.origin() == '+' {
+                let path = delta.new_file().path().unwrap_or_else(|| "".as_ref());
+                lines.push(AddedLine {
+                    path: path.to_string_lossy().into_owned(),
+                    line_number: line.new_lineno().unwrap_or(0),
+                    content: String::from_utf8_lossy(line.content())
+                        .trim_end()
+                        .to_owned(),
+                });
+            }
+            true
+        }),
+    )?;
+    Ok(lines)
+}
+
+/// Returns the lines added by the commit, once its tree is filtered
+///
+/// For merge commits, only the lines that are added compared to every parent are returned,
+/// as the others are brought by the merged commits.
+fn commit_added_lines(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    opts: &app::Options,
+) -> Result<Vec<AddedLine>, git2::Error> {
+    let tree = opts.tree_filter.filter_tree(repo, &commit.tree()?)?;
+    if commit.parent_count() == 0 {
+        return added_lines(repo, None, &tree);
+    }
+
+    let mut parent_trees = Vec::new();
+    for parent in commit.parents() {
+        parent_trees.push(opts.tree_filter.filter_tree(repo, &parent.tree()?)?);
+    }
+
+    let mut lines = added_lines(repo, Some(&parent_trees[0]), &tree)?;
+    for parent_tree in &parent_trees[1..] {
+        let other_lines: HashSet<(String, String)> = added_lines(repo, Some(parent_tree), &tree)?
+            .into_iter()
+            .map(|l| (l.path, l.content))
+            .collect();
+        lines.retain(|l| other_lines.contains(&(l.path.clone(), l.content.clone())));
+    }
+    Ok(lines)
+}
+
+impl SecretScanner {
+    pub fn new(cfg: Option<SecretsCfg>) -> Result<Self, Error> {
+        let cfg = match cfg {
+            Some(cfg) => cfg,
+            None => {
+                return Ok(Self {
+                    patterns: vec![],
+                    entropy: None,
+                    allowed_commits: vec![],
+                })
+            }
+        };
+
+        let mut patterns = Vec::new();
+        for pattern in cfg.patterns.unwrap_or_default() {
+            match regex::Regex::new(&pattern) {
+                Ok(regex) => patterns.push(regex),
+                Err(error) => {
+                    return Err(Error::InvalidConfig {
+                        field: "secrets patterns",
+                        error,
+                    })
+                }
+            }
+        }
+
+        let min_length = cfg.entropy_min_length.unwrap_or(DEFAULT_ENTROPY_MIN_LENGTH);
+        let entropy = cfg.entropy_threshold.map(|threshold| {
+            // words made of characters used in tokens, and base64 or hex encodings
+            let words = format!("[A-Za-z0-9+/=_-]{{{},}}", min_length);
+            // the regex only depends on the length, it cannot fail to compile
+            (threshold, regex::Regex::new(&words).unwrap())
+        });
+
+        Ok(Self {
+            patterns,
+            entropy,
+            allowed_commits: cfg.allowed_commits.unwrap_or_default(),
+        })
+    }
+
+    /// Returns true if the scanner cannot report any secret
+    pub fn is_noop(&self) -> bool {
+        self.patterns.is_empty() && self.entropy.is_none()
+    }
+
+    fn is_allowed(&self, commit: &git2::Commit) -> bool {
+        let id = commit.id().to_string();
+        self.allowed_commits
+            .iter()
+            .any(|allowed| !allowed.is_empty() && id.starts_with(&allowed.to_lowercase()))
+    }
+
+    /// Returns the descriptions of the secrets found in the line, with the secrets masked
+    fn scan_line(&self, line: &str) -> Vec<String> {
+        let mut findings: Vec<String> = self
+            .patterns
+            .iter()
+            .filter_map(|p| p.find(line).map(|m| (p, m)))
+            .map(|(p, m)| format!("'{}' matches pattern '{}'", mask(m.as_str()), p))
+            .collect();
+
+        if let Some((threshold, words)) = &self.entropy {
+            for word in words.find_iter(line) {
+                let entropy = shannon_entropy(word.as_str());
+                if entropy >= *threshold {
+                    findings.push(format!(
+                        "high entropy string '{}' ({:.2} bits per character)",
+                        mask(word.as_str()),
+                        entropy
+                    ));
+                }
+            }
+        }
+        findings
+    }
+
+    /// Scan the changes of the commits about to be synchronized
+    ///
+    /// The secrets found are listed, and an error is returned if any was found in a commit
+    /// that is not allow-listed.
+    pub fn check_commits(
+        &self,
+        repo: &git2::Repository,
+        commits: &[git2::Commit],
+        opts: &app::Options,
+    ) -> Result<(), Error> {
+        if self.is_noop() {
+            return Ok(());
+        }
+
+        let mut nb_findings = 0;
+        for commit in commits {
+            if self.is_allowed(commit) {
+                continue;
+            }
+
+            let mut header_printed = false;
+            for line in commit_added_lines(repo, commit, opts)? {
+                for finding in self.scan_line(&line.content) {
+                    if !header_printed {
+                        info!(opts, "Potential secrets found in commit {}:", commit.id());
+                        header_printed = true;
+                    }
+                    info!(opts, "  {}:{}: {}", line.path, line.line_number, finding);
+                    nb_findings += 1;
+                }
+            }
+            if header_printed {
+                info!(opts);
+            }
+        }
+
+        if nb_findings > 0 {
+            Err(Error::SecretsFound { nb_findings })
+        } else {
+            Ok(())
+        }
+    }
+}
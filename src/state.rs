@@ -0,0 +1,431 @@
+use crate::app;
+use crate::commits_map;
+use crate::error::Error;
+use crate::worktree;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Commit whose synchronization caused conflicts
+pub struct Conflict {
+    // name of the branch being synchronized
+    pub branch: String,
+    // commit of the remote repository
+    pub commit: git2::Oid,
+    pub uprooted: bool,
+    // parents of the copy in the local repository
+    pub parents: Vec<git2::Oid>,
+}
+
+/// State of a synchronization in progress, saved in .git/ripit/state
+///
+/// The state is saved when a synchronization starts, and removed once every branch is
+/// synchronized. It contains the positions of HEAD and of the branches before the
+/// synchronization, so that it can be aborted, and the commit that caused conflicts, if any,
+/// so that the synchronization can be continued once the conflicts are solved.
+pub struct SyncState {
+    // reference or commit HEAD pointed to
+    head: String,
+    // length of the cache file
+    cache_len: u64,
+    // branches and the commits they pointed to
+    branches: Vec<(String, git2::Oid)>,
+    // branches that did not exist, created by the synchronization
+    new_branches: Vec<String>,
+    // branches and the commits they pointed to once the synchronization finished
+    synced: Vec<(String, git2::Oid)>,
+    pub conflict: Option<Conflict>,
+    // temporary worktree containing the conflicts, if the working directory is not used
+    pub worktree: Option<PathBuf>,
+    // whether the working directory of the repository is used by the synchronization
+    pub uses_workdir: bool,
+    // whether the whole history of the new branches is copied
+    pub full_history: bool,
+}
+
+fn state_path(repo: &git2::Repository) -> PathBuf {
+    repo.path().join("ripit").join("state")
+}
+
+/// State of the last finished synchronization, kept so that it can be rolled back
+fn last_state_path(repo: &git2::Repository) -> PathBuf {
+    repo.path().join("ripit").join("last")
+}
+
+fn parse_oid(s: Option<&str>) -> Result<git2::Oid, String> {
+    match git2::Oid::from_str(s.unwrap_or("")) {
+        Ok(oid) => Ok(oid),
+        Err(e) => Err(e.message().to_owned()),
+    }
+}
+
+fn parse_state(content: &str) -> Result<SyncState, String> {
+    let mut state = SyncState {
+        head: String::new(),
+        cache_len: 0,
+        branches: vec![],
+        new_branches: vec![],
+        synced: vec![],
+        conflict: None,
+        worktree: None,
+        uses_workdir: true,
+        full_history: false,
+    };
+
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("head") => state.head = parts.next().unwrap_or("").to_owned(),
+            Some("cache") => match parts.next().unwrap_or("").parse() {
+                Ok(len) => state.cache_len = len,
+                Err(e) => return Err(format!("invalid cache length: {}", e)),
+            },
+            Some("branch") => {
+                let refname = parts.next().unwrap_or("").to_owned();
+                state.branches.push((refname, parse_oid(parts.next())?));
+            }
+            Some("new") => state
+                .new_branches
+                .push(parts.next().unwrap_or("").to_owned()),
+            Some("synced") => {
+                let refname = parts.next().unwrap_or("").to_owned();
+                state.synced.push((refname, parse_oid(parts.next())?));
+            }
+            Some("isolated") => state.uses_workdir = false,
+            Some("full-history") => state.full_history = true,
+            Some("worktree") => {
+                // the path can contain spaces
+                state.worktree = Some(PathBuf::from(line["worktree ".len()..].to_owned()));
+            }
+            Some("conflict") => {
+                let branch = parts.next().unwrap_or("").to_owned();
+                let commit = parse_oid(parts.next())?;
+                let uprooted = parts.next() == Some("uprooted");
+                let parents = parts
+                    .map(|p| parse_oid(Some(p)))
+                    .collect::<Result<_, _>>()?;
+                state.conflict = Some(Conflict {
+                    branch,
+                    commit,
+                    uprooted,
+                    parents,
+                });
+            }
+            _ => return Err(format!("invalid line \"{}\"", line)),
+        }
+    }
+
+    if state.head.is_empty() {
+        return Err("missing head".to_owned());
+    }
+    Ok(state)
+}
+
+fn remove_file(path: PathBuf) -> Result<(), Error> {
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(Error::StateFileError { err, path }),
+        },
+    }
+}
+
+impl SyncState {
+    /// Load the state of the synchronization in progress, if any
+    pub fn load(repo: &git2::Repository) -> Result<Option<Self>, Error> {
+        Self::load_from(state_path(repo))
+    }
+
+    /// Load the state of the last finished synchronization, if any
+    pub fn load_last(repo: &git2::Repository) -> Result<Option<Self>, Error> {
+        Self::load_from(last_state_path(repo))
+    }
+
+    fn load_from(path: PathBuf) -> Result<Option<Self>, Error> {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => return Err(Error::StateFileError { err, path }),
+            },
+        };
+
+        match parse_state(&content) {
+            Ok(state) => Ok(Some(state)),
+            Err(desc) => Err(Error::InvalidStateFile { desc, path }),
+        }
+    }
+
+    /// Start a synchronization
+    ///
+    /// If a previous synchronization was interrupted, its state is kept, so that aborting
+    /// restores the positions from before the interrupted synchronization. Otherwise, the
+    /// current positions of HEAD and of the branches are saved, with whether the working
+    /// directory of the repository is used.
+    pub fn begin(
+        repo: &git2::Repository,
+        opts: &app::Options,
+        uses_workdir: bool,
+    ) -> Result<(), Error> {
+        let mut state = match Self::load(repo)? {
+            Some(state) => state,
+            None => {
+                let head = repo.find_reference("HEAD")?;
+                let head = match head.symbolic_target() {
+                    Some(refname) => refname.to_owned(),
+                    None => head.target().map(|o| o.to_string()).unwrap_or_default(),
+                };
+                let cache_len = match std::fs::metadata(commits_map::cache_path(repo)) {
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => 0,
+                };
+
+                let mut branches = Vec::new();
+                let mut new_branches = Vec::new();
+                for branch in &opts.branches {
+                    match repo.refname_to_id(&branch.refname) {
+                        Ok(oid) => branches.push((branch.refname.clone(), oid)),
+                        Err(_) => new_branches.push(branch.refname.clone()),
+                    }
+                }
+
+                Self {
+                    head,
+                    cache_len,
+                    branches,
+                    new_branches,
+                    synced: Vec::new(),
+                    conflict: None,
+                    worktree: None,
+                    uses_workdir,
+                    full_history: opts.full_history,
+                }
+            }
+        };
+
+        // conflicts of the previous synchronization were committed by the user
+        state.conflict = None;
+        state.save(repo)
+    }
+
+    pub fn save(&self, repo: &git2::Repository) -> Result<(), Error> {
+        let path = state_path(repo);
+        let mut content = format!("head {}\ncache {}\n", self.head, self.cache_len);
+        for (refname, oid) in &self.branches {
+            content.push_str(&format!("branch {} {}\n", refname, oid));
+        }
+        for refname in &self.new_branches {
+            content.push_str(&format!("new {}\n", refname));
+        }
+        for (refname, oid) in &self.synced {
+            content.push_str(&format!("synced {} {}\n", refname, oid));
+        }
+        if !self.uses_workdir {
+            content.push_str("isolated\n");
+        }
+        if self.full_history {
+            content.push_str("full-history\n");
+        }
+        if let Some(path) = &self.worktree {
+            content.push_str(&format!("worktree {}\n", path.display()));
+        }
+        if let Some(conflict) = &self.conflict {
+            let parents: Vec<String> = conflict.parents.iter().map(|p| p.to_string()).collect();
+            content.push_str(&format!(
+                "conflict {} {} {} {}\n",
+                conflict.branch,
+                conflict.commit,
+                if conflict.uprooted {
+                    "uprooted"
+                } else {
+                    "rooted"
+                },
+                parents.join(" ")
+            ));
+        }
+
+        let res = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| std::fs::File::create(&path))
+            .and_then(|mut file| file.write_all(content.as_bytes()));
+        match res {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::StateFileError { err, path }),
+        }
+    }
+
+    /// Returns true if a branch or the cache file changed since the synchronization started
+    fn has_changes(&self, repo: &git2::Repository) -> Result<bool, Error> {
+        for (refname, oid) in &self.branches {
+            if repo.refname_to_id(refname).ok() != Some(*oid) {
+                return Ok(true);
+            }
+        }
+        for refname in &self.new_branches {
+            if repo.find_reference(refname).is_ok() {
+                return Ok(true);
+            }
+        }
+        let cache_len = match std::fs::metadata(commits_map::cache_path(repo)) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+        Ok(cache_len != self.cache_len)
+    }
+
+    /// Mark the synchronization as finished, keeping its state so that it can be rolled back
+    ///
+    /// If it did not change anything, the state of the previous synchronization is kept
+    /// instead, so that a synchronization without new commits does not prevent rolling back
+    /// the last one. Otherwise, the new positions of the branches are saved, so that commits
+    /// made on them since are not dropped by a rollback.
+    pub fn finish(repo: &git2::Repository) -> Result<(), Error> {
+        if let Some(mut state) = Self::load(repo)? {
+            if !state.has_changes(repo)? {
+                return Self::end(repo);
+            }
+            let refnames = state.branches.iter().map(|(refname, _)| refname);
+            state.synced = refnames
+                .chain(&state.new_branches)
+                .filter_map(|refname| {
+                    let oid = repo.refname_to_id(refname).ok()?;
+                    Some((refname.clone(), oid))
+                })
+                .collect();
+            state.save(repo)?;
+        }
+        let path = state_path(repo);
+        match std::fs::rename(&path, last_state_path(repo)) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::StateFileError { err, path }),
+        }
+    }
+
+    /// Mark the synchronization as abandoned
+    pub fn end(repo: &git2::Repository) -> Result<(), Error> {
+        remove_file(state_path(repo))
+    }
+
+    /// Forget the last finished synchronization, once rolled back
+    pub fn forget_last(repo: &git2::Repository) -> Result<(), Error> {
+        remove_file(last_state_path(repo))
+    }
+
+    /// Save the commit whose synchronization caused conflicts, and the worktree containing
+    /// them if not in the repository itself
+    pub fn record_conflict(
+        repo: &git2::Repository,
+        conflict: Conflict,
+        worktree: Option<PathBuf>,
+    ) -> Result<(), Error> {
+        if let Some(mut state) = Self::load(repo)? {
+            state.remove_worktree(repo)?;
+            state.conflict = Some(conflict);
+            state.worktree = worktree;
+            state.save(repo)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the commit the branch pointed to before the synchronization
+    pub fn branch_id(&self, refname: &str) -> Option<git2::Oid> {
+        self.branches
+            .iter()
+            .find(|(name, _)| name == refname)
+            .map(|(_, oid)| *oid)
+    }
+
+    /// Check that the branches still point to the commits they pointed to once the
+    /// synchronization finished
+    pub fn check_synced_branches(&self, repo: &git2::Repository) -> Result<(), Error> {
+        for (refname, oid) in &self.synced {
+            if repo.refname_to_id(refname).ok() != Some(*oid) {
+                return Err(Error::BranchMovedSinceSync {
+                    branch: refname.trim_start_matches("refs/heads/").to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the branch was created by the synchronization
+    pub fn is_new_branch(&self, refname: &str) -> bool {
+        self.new_branches.iter().any(|name| name == refname)
+    }
+
+    /// Returns true if the whole history of a branch created by the synchronization is being
+    /// copied, and copies were already staged
+    pub fn is_copying_history(&self, repo: &git2::Repository, opts: &app::Options) -> bool {
+        self.full_history
+            && opts.branches.iter().any(|branch| {
+                self.is_new_branch(&branch.refname)
+                    && repo.find_reference(&branch.staging_refname()).is_ok()
+            })
+    }
+
+    /// Remove the temporary worktree of the synchronization, if any
+    pub fn remove_worktree(&mut self, repo: &git2::Repository) -> Result<(), Error> {
+        match self.worktree.take() {
+            Some(path) => worktree::remove(repo, &path),
+            None => Ok(()),
+        }
+    }
+
+    /// Move HEAD back on the branch or the commit it pointed to before the synchronization
+    ///
+    /// Neither the index nor the working directory are updated.
+    pub fn reset_head(&self, repo: &git2::Repository) -> Result<(), Error> {
+        match git2::Oid::from_str(&self.head) {
+            Ok(oid) if !self.head.starts_with("refs/") => repo.set_head_detached(oid)?,
+            _ => repo.set_head(&self.head)?,
+        }
+        Ok(())
+    }
+
+    /// Move HEAD back on the branch or the commit it pointed to before the synchronization
+    ///
+    /// The working directory is updated to match it. If HEAD was on one of the synchronized
+    /// branches, it is thus fast-forwarded on the copies.
+    pub fn restore_head(&self, repo: &git2::Repository) -> Result<(), Error> {
+        self.reset_head(repo)?;
+
+        // nothing to checkout if HEAD is on a branch yet to be created
+        if repo.head().is_ok() {
+            let mut checkout_opts = git2::build::CheckoutBuilder::new();
+            checkout_opts.force();
+            repo.checkout_head(Some(&mut checkout_opts))?;
+        }
+        Ok(())
+    }
+
+    /// Restore HEAD, the branches and the cache file as they were before the synchronization
+    ///
+    /// The staged copies are dropped, and the branches created by the synchronization are
+    /// deleted. The working directory is only reset if it was used by the synchronization.
+    pub fn restore(&mut self, repo: &git2::Repository) -> Result<(), Error> {
+        for (refname, oid) in &self.branches {
+            let msg = "ripit: restore branch";
+            // a finished synchronization only restores the branches it left untouched since
+            match self.synced.iter().find(|(name, _)| name == refname) {
+                Some((_, synced)) => repo.reference_matching(refname, *oid, true, *synced, msg)?,
+                None => repo.reference(refname, *oid, true, msg)?,
+            };
+        }
+        for refname in &self.new_branches {
+            if let Ok(mut reference) = repo.find_reference(refname) {
+                reference.delete()?;
+            }
+        }
+        for reference in repo.references_glob("refs/ripit/staging/*")? {
+            reference?.delete()?;
+        }
+        self.remove_worktree(repo)?;
+        if !self.uses_workdir {
+            return commits_map::truncate_cache_file(repo, self.cache_len);
+        }
+
+        self.restore_head(repo)?;
+        repo.cleanup_state()?;
+
+        commits_map::truncate_cache_file(repo, self.cache_len)
+    }
+}
@@ -0,0 +1,168 @@
+use crate::app;
+use crate::commits_map::CommitsMap;
+use crate::error::Error;
+use crate::report;
+use crate::sync;
+use crate::tag;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Parent of a commit to synchronize that is neither synchronized, nor to be synchronized
+#[derive(Serialize)]
+struct UnknownParent {
+    commit: String,
+    parent: String,
+}
+
+/// State of the synchronization of a branch
+#[derive(Serialize)]
+struct BranchStatus {
+    branch: String,
+    bootstrapped: bool,
+    // remote commit the last commit of the local branch was copied from
+    last_synced: Option<String>,
+    // synchronized remote commit a branch that does not exist yet will be created from
+    forks_from: Option<String>,
+    // number of commits to synchronize
+    pending: usize,
+    // whether the synchronization requires --uproot
+    uproot_needed: bool,
+    unknown_parents: Vec<UnknownParent>,
+    // root commits to synchronize, that can only be uprooted
+    unknown_roots: Vec<String>,
+}
+
+/// Find the commits that can only be copied by uprooting them
+///
+/// The parents of the commits are mapped as when copying them, the commits being synchronized
+/// in order.
+fn find_uproots(
+    status: &mut BranchStatus,
+    commits: &[git2::Commit],
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) {
+    let mut pending = HashMap::new();
+
+    for commit in commits {
+        let mapping = commits_map.map_parents(commit, &pending, opts);
+        for parent_id in &mapping.unknown {
+            status.unknown_parents.push(UnknownParent {
+                commit: commit.id().to_string(),
+                parent: parent_id.to_string(),
+            });
+        }
+        if mapping.uproot_needed && mapping.unknown.is_empty() {
+            status.unknown_roots.push(commit.id().to_string());
+        }
+        status.uproot_needed |= mapping.uproot_needed;
+        pending.insert(commit.id(), mapping.uprooted);
+    }
+}
+
+fn branch_status(
+    repo: &git2::Repository,
+    branch: &app::Branch,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> Result<BranchStatus, Error> {
+    let mut status = BranchStatus {
+        branch: branch.name.clone(),
+        bootstrapped: false,
+        last_synced: None,
+        forks_from: None,
+        pending: 0,
+        uproot_needed: false,
+        unknown_parents: vec![],
+        unknown_roots: vec![],
+    };
+    let remote_branch = repo.revparse_single(&format!("{}/{}", opts.remote, branch.name))?;
+
+    let local_commit = if sync::branch_exists(repo, branch) {
+        let local_commit = sync::local_tip(repo, branch)?;
+        let (last_synced, _) = tag::retrieve_ripit_tag_or_throw(&local_commit)?;
+        status.bootstrapped = true;
+        status.last_synced = Some(last_synced);
+        local_commit
+    } else {
+        // the synchronization creates the branch from the commit it forks from
+        let remote_commit = remote_branch.peel_to_commit()?;
+        match sync::find_fork_point(repo, &remote_commit, commits_map)? {
+            Some((fork_id, local_commit)) => {
+                status.forks_from = Some(fork_id.to_string());
+                local_commit
+            }
+            None => return Ok(status),
+        }
+    };
+
+    let commits =
+        sync::find_commits_to_sync(repo, local_commit.id(), &remote_branch, commits_map, opts)?;
+    status.pending = commits.len();
+    find_uproots(&mut status, &commits, commits_map, opts);
+    Ok(status)
+}
+
+/// Print the state of the synchronization of every branch, without modifying the repository
+///
+/// Only the remote is fetched, unless disabled.
+pub fn print_status(repo: &git2::Repository, opts: &app::Options) -> Result<(), Error> {
+    if opts.fetch {
+        sync::update_remote(repo, opts)?;
+    }
+
+    let mut commits_map = CommitsMap::new(repo, true)?;
+    for branch in &opts.branches {
+        // the branch may not be bootstrapped yet
+        if repo.find_reference(&branch.refname).is_ok() {
+            commits_map.fill_from_branch(repo, &branch.name)?;
+        }
+        // copies staged by an unfinished synchronization
+        let staging_refname = branch.staging_refname();
+        if repo.find_reference(&staging_refname).is_ok() {
+            commits_map.fill_from_branch(repo, &staging_refname)?;
+        }
+    }
+
+    for branch in &opts.branches {
+        let status = branch_status(repo, branch, &commits_map, opts)?;
+
+        if opts.output == app::Output::Json {
+            report::print_json(&status);
+            continue;
+        }
+
+        info!(opts, "Branch {}:", status.branch);
+        if let Some(last_synced) = &status.last_synced {
+            info!(opts, "  Last synchronized private commit {}", last_synced);
+        } else if let Some(forks_from) = &status.forks_from {
+            info!(
+                opts,
+                "  Not created yet, forks from synchronized private commit {}", forks_from
+            );
+        } else {
+            info!(opts, "  Not bootstrapped");
+            continue;
+        }
+        if status.pending == 0 {
+            info!(opts, "  Up to date with {}", opts.remote);
+        } else {
+            info!(opts, "  {} commits to synchronize", status.pending);
+        }
+        for unknown in &status.unknown_parents {
+            info!(
+                opts,
+                "  Commit {} has an unknown parent {}, it must be uprooted",
+                unknown.commit,
+                unknown.parent
+            );
+        }
+        for root in &status.unknown_roots {
+            info!(
+                opts,
+                "  Commit {} is a root commit, it must be uprooted", root
+            );
+        }
+    }
+    Ok(())
+}
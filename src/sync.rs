@@ -1,10 +1,17 @@
 use crate::app;
-use crate::commits_map::{CommitsMap, SyncedCommit};
+use crate::commits_map::{is_copied_as_root, CommitsMap, SyncedCommit};
 use crate::error::Error;
+use crate::filter::EmptyCommits;
+use crate::journal;
+use crate::message;
+use crate::report;
+use crate::state;
 use crate::tag;
 use crate::util;
+use crate::worktree;
+use std::collections::HashMap;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 // {{{ Fetch remote
 
@@ -13,7 +20,10 @@ pub fn update_remote(repo: &git2::Repository, opts: &app::Options) -> Result<(),
 
     for branch in &opts.branches {
         if opts.verbose {
-            println!("Fetch branch {} in remote {}...", branch.name, opts.remote);
+            info!(
+                opts,
+                "Fetch branch {} in remote {}...", branch.name, opts.remote
+            );
         }
         if let Err(e) = remote.fetch(&[&branch.name], None, None) {
             eprintln!("Fetch failed. Consider running `git fetch {}` \
@@ -51,7 +61,7 @@ fn build_revwalk<'a>(
 /// * rewind to the last non-uprooted commit, so that a sensical revwalk
 ///   can be built.
 /// * ignore the already uprooted commits from the revwalk.
-fn find_commits_to_sync<'a>(
+pub fn find_commits_to_sync<'a>(
     repo: &'a git2::Repository,
     local_commit: git2::Oid,
     remote_commit: &git2::Object,
@@ -76,9 +86,12 @@ fn find_commits_to_sync<'a>(
     }
     if opts.verbose {
         if cnt > 0 {
-            println!("Rewinding {} commits to ignore uprooted ones.", cnt);
+            info!(opts, "Rewinding {} commits to ignore uprooted ones.", cnt);
         }
-        println!("Found ripit tag, last synced commit was {}.", last_tag);
+        info!(
+            opts,
+            "Found ripit tag, last synced commit was {}.", last_tag
+        );
     }
 
     // Get the commit related to this SHA-1
@@ -91,56 +104,65 @@ fn find_commits_to_sync<'a>(
         if !commits_map.contains_key(oid) {
             commits.push(repo.find_commit(oid)?);
         } else if opts.verbose {
-            println!("Ignoring {}: commit already synchronized.", oid);
+            info!(opts, "Ignoring {}: commit already synchronized.", oid);
         }
     }
 
     Ok(commits)
 }
 
+/// Build a list of every commit of the remote branch that is not synchronized yet
+///
+/// This is used to copy the whole history of the branch, from its root commits.
+fn find_all_commits_to_sync<'a>(
+    repo: &'a git2::Repository,
+    remote_commit: &git2::Object,
+    commits_map: &CommitsMap,
+) -> Result<Vec<git2::Commit<'a>>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE);
+    revwalk.push(remote_commit.id())?;
+
+    let mut commits = vec![];
+    for oid in revwalk {
+        let oid = oid?;
+        if !commits_map.contains_key(oid) {
+            commits.push(repo.find_commit(oid)?);
+        }
+    }
+    Ok(commits)
+}
+
 // }}}
 // {{{ Sync branch
 
+/// Make the working directory match HEAD
+///
+/// Bare repositories do not have a working directory, nothing is done for them.
 fn force_checkout_head(repo: &git2::Repository) -> Result<(), git2::Error> {
+    if repo.is_bare() {
+        return Ok(());
+    }
     let mut opts = git2::build::CheckoutBuilder::new();
     opts.force();
     repo.checkout_head(Some(&mut opts))
 }
 
-fn filter_commit_msg(msg: &str, opts: &app::Options) -> String {
-    if opts.commit_msg_filters.len() == 0 {
-        return msg.to_owned();
-    }
-
-    let new_lines: Vec<&str> = msg
-        .lines()
-        .filter(|line| {
-            if opts.commit_msg_filters.is_match(line) {
-                if opts.verbose {
-                    println!("  Filtering out line '{}'", line);
-                }
-                false
-            } else {
-                true
-            }
-        })
-        .collect();
-
-    new_lines.join("\n")
-}
-
-// TODO: use a string builder, to avoid the double alloc
-fn update_commit_msg(orig_msg: &str, tag: &str, opts: &app::Options) -> String {
-    let orig_msg = filter_commit_msg(orig_msg, &opts);
-    if orig_msg.ends_with("\n") {
-        format!("{}\n{}\n", orig_msg, tag)
-    } else {
-        format!("{}\n\n{}\n", orig_msg, tag)
-    }
+/// Returns whether the commits are copied in the working directory of the repository
+///
+/// They are not for bare repositories, nor with `--worktree`: only the branches are then
+/// updated, and conflicts are left in a temporary worktree.
+pub fn uses_workdir(repo: &git2::Repository, opts: &app::Options) -> bool {
+    !repo.is_bare() && !opts.worktree
 }
 
 /// Append the tag to .git/MERGE_MSG, if it exists
-fn update_merge_msg(repo: &git2::Repository, tag: &str, opts: &app::Options) {
+fn update_merge_msg(
+    repo: &git2::Repository,
+    tag: &str,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) {
     let path = Path::new(repo.path()).join("MERGE_MSG");
     let msg = match std::fs::read_to_string(&path) {
         Ok(c) => c,
@@ -151,7 +173,7 @@ fn update_merge_msg(repo: &git2::Repository, tag: &str, opts: &app::Options) {
     };
 
     // TODO: use a string builder
-    let msg = update_commit_msg(&msg, tag, opts);
+    let msg = message::update_commit_msg(&msg, tag, repo, commits_map, opts);
 
     if let Ok(mut file) = std::fs::File::create(&path) {
         if let Err(e) = write!(file, "{}", &msg) {
@@ -193,162 +215,496 @@ fn fix_merge_ctx(repo: &git2::Repository, commit_id: git2::Oid) -> bool {
     true
 }
 
+/// Build the commit to cherry-pick in the local repository
+///
+/// This is a copy of the remote commit, with the transformations applied on its content:
+/// * its tree and the trees of its parents are filtered, so that the cherry-pick only brings
+///   the changes made on the paths that are not filtered out.
+/// * its author and committer are redacted. When a cherry-pick causes conflicts, the author
+///   of the commit referenced by CHERRY_PICK_HEAD is used when the user commits the
+///   resolution, so the redacted identities are used in this case as well.
+fn build_source_commit<'a>(
+    repo: &'a git2::Repository,
+    commit: &git2::Commit,
+    opts: &app::Options,
+) -> Result<git2::Commit<'a>, git2::Error> {
+    if opts.identities.is_noop() && opts.tree_filter.is_noop() {
+        return repo.find_commit(commit.id());
+    }
+
+    let author = opts.identities.rewrite(&commit.author())?;
+    let committer = opts.identities.rewrite(&commit.committer())?;
+
+    // The parents are only used to compute the changes brought by the commit, they can
+    // be replaced by commits containing their filtered trees.
+    let mut parents = Vec::new();
+    for parent_id in commit.parent_ids() {
+        let parent = repo.find_commit(parent_id)?;
+        if opts.tree_filter.is_noop() {
+            parents.push(parent);
+        } else {
+            let tree = opts.tree_filter.filter_tree(repo, &parent.tree()?)?;
+            let oid = repo.commit(None, &author, &committer, "", &tree, &[])?;
+            parents.push(repo.find_commit(oid)?);
+        }
+    }
+    let parents: Vec<&git2::Commit> = parents.iter().collect();
+
+    let oid = repo.commit(
+        None,
+        &author,
+        &committer,
+        commit.message().unwrap_or(""),
+        &opts.tree_filter.filter_tree(repo, &commit.tree()?)?,
+        &parents,
+    )?;
+    repo.find_commit(oid)
+}
+
+/// Returns true if the commit has changes, but all of them are filtered out
+///
+/// The changes of a merge are the ones made against its mainline. A merge is only filtered
+/// out if the copies of its other parents are already in the history of the copy of its
+/// mainline, `base_id`, so that dropping it does not leave copies out of the branch. This
+/// is the case when all the commits it merges were dropped as well.
+fn is_filtered_out(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    source: &git2::Commit,
+    mainline: usize,
+    local_parents: &[&git2::Commit],
+    base_id: git2::Oid,
+) -> Result<bool, git2::Error> {
+    let parent = mainline.saturating_sub(1);
+    if commit.tree_id() == commit.parent(parent)?.tree_id()
+        || source.tree_id() != source.parent(parent)?.tree_id()
+    {
+        return Ok(false);
+    }
+    for local_parent in local_parents {
+        if local_parent.id() != base_id && !repo.graph_descendant_of(base_id, local_parent.id())? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the local commit to map the commit on if it is dropped, as all of its changes are
+/// filtered out
+///
+/// This is the copy of its mainline, on which it would have been created. Root commits are
+/// never dropped.
+fn find_drop_base(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    source: &git2::Commit,
+    local_parents: &[&git2::Commit],
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> Result<Option<git2::Oid>, Error> {
+    if opts.empty_commits != EmptyCommits::Drop || commit.parent_count() == 0 {
+        return Ok(None);
+    }
+
+    let mainline = find_mainline(commit, commits_map, opts)?;
+    let base_id = find_base_commit(commit, mainline, local_parents, commits_map);
+    if is_filtered_out(repo, commit, source, mainline, local_parents, base_id)? {
+        Ok(Some(base_id))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Find the parent to use as mainline when copying the commit
+///
+/// Returns the number of the parent, starting from 1, or 0 if the commit is not a merge.
+/// Unless a mainline is configured for the commit, the first parent with a non-uprooted copy
+/// is used, or failing that, the first parent with a copy. The copy of the merge is built
+/// on top of the copy of this parent, so that the changes brought by the merge are computed
+/// against the content it is applied on.
+fn find_mainline(
+    commit: &git2::Commit,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> Result<usize, Error> {
+    let nb_parents = commit.parent_count();
+    if nb_parents < 2 {
+        return Ok(0);
+    }
+
+    let id = commit.id().to_string();
+    let cfg = opts
+        .mainlines
+        .iter()
+        .find(|m| !m.commit.is_empty() && id.starts_with(&m.commit.to_lowercase()));
+    if let Some(cfg) = cfg {
+        if cfg.parent == 0 || cfg.parent > nb_parents {
+            return Err(Error::InvalidMainline {
+                commit_id: commit.id(),
+                parent: cfg.parent,
+                nb_parents,
+            });
+        }
+        return Ok(cfg.parent);
+    }
+
+    let mapped: Vec<Option<bool>> = commit
+        .parent_ids()
+        .map(|id| commits_map.get(id).map(|p| p.uprooted))
+        .collect();
+    let pos = match mapped.iter().position(|p| *p == Some(false)) {
+        Some(pos) => Some(pos),
+        None => mapped.iter().position(|p| p.is_some()),
+    };
+    Ok(pos.unwrap_or(0) + 1)
+}
+
+/// Returns the local commit the copy must be built on
+///
+/// This is the copy of the mainline parent if it exists, or the first local parent otherwise.
+fn find_base_commit(
+    commit: &git2::Commit,
+    mainline: usize,
+    local_parents: &[&git2::Commit],
+    commits_map: &CommitsMap,
+) -> git2::Oid {
+    if mainline > 0 {
+        if let Some(parent) = commit
+            .parent_ids()
+            .nth(mainline - 1)
+            .and_then(|id| commits_map.get(id))
+        {
+            return parent.commit.id();
+        }
+    }
+    local_parents[0].id()
+}
+
 fn do_cherrypick<'a, 'b>(
     repo: &'a git2::Repository,
     commit: &'b git2::Commit,
     local_parents: &Vec<&'b git2::Commit>,
-    is_merge: bool,
     uprooted: bool,
+    commits_map: &CommitsMap,
     branch: &app::Branch,
     opts: &app::Options,
-) -> Result<git2::Commit<'a>, Error> {
-    let branch_id = repo.refname_to_id(&branch.refname)?;
-    let update_branch = local_parents[0].id() == branch_id;
-
-    // checkout parent, then cherrypick on top of it
-    if update_branch {
-        repo.set_head(&branch.refname)?;
-    } else {
-        repo.set_head_detached(local_parents[0].id())?;
+) -> Result<CopiedCommit<'a>, Error> {
+    let source = build_source_commit(repo, commit, opts)?;
+    if let Some(base_id) = find_drop_base(repo, commit, &source, local_parents, commits_map, opts)?
+    {
+        return drop_commit(repo, base_id);
     }
-    force_checkout_head(&repo)?;
+
+    let mainline = find_mainline(commit, commits_map, opts)?;
+    let base_id = find_base_commit(commit, mainline, local_parents, commits_map);
 
     let tag = tag::format_ripit_tag(commit, uprooted);
 
-    // cherrypick changes on top of HEAD
-    let mut cherrypick_opts = git2::CherrypickOptions::new();
-    if is_merge {
-        // TODO: find the right mainline
-        cherrypick_opts.mainline(1);
-    }
-    repo.cherrypick(&commit, Some(&mut cherrypick_opts))?;
+    // cherrypick changes on top of the base, in memory
+    let base = repo.find_commit(base_id)?;
+    let mut index = repo.cherrypick_commit(&source, &base, mainline as u32, None)?;
+    if index.has_conflicts() {
+        let worktree = checkout_for_conflicts(repo, base_id, opts)?;
+        let worktree_repo = match &worktree {
+            Some(path) => Some(git2::Repository::open(path)?),
+            None => None,
+        };
+        let work_repo = worktree_repo.as_ref().unwrap_or(repo);
+
+        // redo the cherrypick in the working directory, so that the user can solve the
+        // conflicts
+        let mut cherrypick_opts = git2::CherrypickOptions::new();
+        cherrypick_opts.mainline(mainline as u32);
+        work_repo.cherrypick(&source, Some(&mut cherrypick_opts))?;
 
-    if repo.index()?.has_conflicts() {
         // The commit message is written in .git/MERGE_MSG, and will be
         // used when the user commits the changes.
         // It must thus be updated to:
         //  - apply the filters
         //  - add the ripit-tag
-        update_merge_msg(repo, &tag, &opts);
-
-        if is_merge && local_parents.len() > 1 {
-            if !fix_merge_ctx(repo, local_parents[1].id()) {
-                return Err(Error::CannotSetupMergeCtx);
+        update_merge_msg(work_repo, &tag, commits_map, opts);
+
+        if commit.parent_count() > 1 {
+            // the merged commit is the local parent that is not checked out. If there is
+            // none, the merge is copied with a single parent.
+            if let Some(merged) = local_parents.iter().find(|p| p.id() != base_id) {
+                if !fix_merge_ctx(work_repo, merged.id()) {
+                    return Err(Error::CannotSetupMergeCtx);
+                }
             }
         }
 
+        // save the commit, so that the synchronization can be continued once the
+        // conflicts are solved
+        state::SyncState::record_conflict(
+            repo,
+            state::Conflict {
+                branch: branch.name.clone(),
+                commit: commit.id(),
+                uprooted,
+                parents: local_parents.iter().map(|p| p.id()).collect(),
+            },
+            worktree.clone(),
+        )?;
+
         return Err(Error::HasConflicts {
             summary: commit.summary().unwrap_or("").to_owned(),
+            worktree,
         });
     }
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
 
     let new_msg = match commit.message() {
-        Some(orig_msg) => update_commit_msg(orig_msg, &tag, opts),
+        Some(orig_msg) => message::update_commit_msg(orig_msg, &tag, repo, commits_map, opts),
         None => tag,
     };
-    // if the first parent is the branch's head, then directly
-    // update the branch when committing
-    let update_ref = if update_branch {
-        &branch.refname
-    } else {
-        "HEAD"
-    };
 
-    // commit the changes
-    let tree_oid = repo.index()?.write_tree()?;
-    let tree = repo.find_tree(tree_oid)?;
+    let new_commit = commit_copy(repo, &source, &new_msg, &tree, local_parents, branch, opts)?;
+    print_created_commit(&new_commit, uprooted, opts);
+
+    Ok(CopiedCommit::Created(SyncedCommit {
+        commit: new_commit,
+        uprooted,
+    }))
+}
+
+/// Checkout the base of a commit whose cherry-pick causes conflicts
+///
+/// The base is checked out in the working directory of the repository, or in a temporary
+/// worktree if it is not used, whose path is returned.
+fn checkout_for_conflicts(
+    repo: &git2::Repository,
+    base_id: git2::Oid,
+    opts: &app::Options,
+) -> Result<Option<PathBuf>, Error> {
+    if !uses_workdir(repo, opts) {
+        return Ok(Some(worktree::add(repo, base_id)?));
+    }
+
+    repo.set_head_detached(base_id)?;
+    force_checkout_head(repo)?;
+    Ok(None)
+}
+
+/// Commit the tree on top of the local parents, with the identities of the source commit
+///
+/// HEAD is detached on the new commit, but the working directory is not updated.
+fn commit_copy<'a>(
+    repo: &'a git2::Repository,
+    source: &git2::Commit,
+    msg: &str,
+    tree: &git2::Tree,
+    local_parents: &[&git2::Commit],
+    branch: &app::Branch,
+    opts: &app::Options,
+) -> Result<git2::Commit<'a>, Error> {
     let ci_oid = repo.commit(
-        Some(update_ref),
-        &commit.author(),
-        &commit.committer(),
-        &new_msg,
-        &tree,
-        &local_parents,
+        None,
+        &source.author(),
+        &source.committer(),
+        msg,
+        tree,
+        local_parents,
     )?;
 
     let new_commit = repo.find_commit(ci_oid)?;
+
+    // if one of the parents is the staged branch, then update it. When copying the whole
+    // history, the branch is staged on the first copy.
+    //
+    // The staged branch is not always the checked out parent: when syncing merge commits, we
+    // will first synchronize the second branch, and update the staged branch, then synchronize
+    // the merge commit on top of its mainline. We need to fix the staged branch back to the
+    // merge commit.
+    if !branch_exists(repo, branch) {
+        stage_branch(repo, branch, &new_commit)?;
+    } else {
+        let branch_id = local_tip(repo, branch)?.id();
+        if local_parents.iter().any(|p| p.id() == branch_id) {
+            stage_branch(repo, branch, &new_commit)?;
+        }
+    }
+
+    // only the refs are updated when the working directory is not used
+    if uses_workdir(repo, opts) {
+        repo.set_head_detached(new_commit.id())?;
+    }
+
+    Ok(new_commit)
+}
+
+fn print_created_commit(commit: &git2::Commit, uprooted: bool, opts: &app::Options) {
     if uprooted {
-        println!("Uprooted commit {}.", new_commit.id());
+        info!(opts, "Uprooted commit {}.", commit.id());
     } else {
-        println!("Created commit {}.", new_commit.id());
+        info!(opts, "Created commit {}.", commit.id());
     }
+}
 
-    // if one of the following parents was the local branch, then update it.
-    //
-    // This can happen when syncing merge commits, as we will first synchronize the second
-    // branch, and update the local branch, then synchronize the merge commit. We need to
-    // fix the local branch back to the merge commit.
-    if !update_branch && local_parents.iter().any(|p| p.id() == branch_id) {
-        repo.branch(&branch.name, &new_commit, true)?;
-        repo.set_head(&branch.refname)?;
+enum CopiedCommit<'a> {
+    // A new commit was created in the local repository
+    Created(SyncedCommit<'a>),
+    // The commit was not copied, as all of its changes were filtered out. It is mapped to the
+    // local commit it would have been created on.
+    Dropped(SyncedCommit<'a>),
+}
+
+/// Find the parents of the commit in the local repository
+///
+/// The local parents are returned, alongside whether the commit is uprooted. If none of the
+/// parents are known, the list is empty, and the commit must be uprooted.
+///
+/// Parents mapped on the same local commit are only listed once, for example when a merged
+/// branch was entirely dropped: such merges are copied with a single parent.
+fn find_local_parents<'b>(
+    commit: &git2::Commit,
+    commits_map: &'b CommitsMap,
+    opts: &app::Options,
+) -> Result<(Vec<&'b git2::Commit<'b>>, bool), Error> {
+    let mapping = commits_map.map_parents(commit, &HashMap::new(), opts);
+    if mapping.uproot_needed && !opts.uproot {
+        return Err(match mapping.unknown.first() {
+            Some(parent_id) => Error::UnknownParent {
+                commit_id: commit.id(),
+                parent_id: *parent_id,
+            },
+            None => Error::UnknownRoot {
+                commit_id: commit.id(),
+            },
+        });
     }
 
-    // make the working directory match HEAD
-    force_checkout_head(&repo)?;
-    repo.cleanup_state()?;
+    let mut local_parents = Vec::new();
+    for parent_id in commit.parent_ids() {
+        if let Some(parent_ci) = commits_map.get(parent_id) {
+            let copy_id = parent_ci.commit.id();
+            if !local_parents
+                .iter()
+                .any(|p: &&git2::Commit| p.id() == copy_id)
+            {
+                local_parents.push(&parent_ci.commit);
+            }
+        }
+    }
+    Ok((local_parents, mapping.uprooted))
+}
 
-    Ok(new_commit)
+/// Returns the mapping of a dropped commit, on the local commit it would have been created on
+fn drop_commit<'a>(
+    repo: &'a git2::Repository,
+    local_parent: git2::Oid,
+) -> Result<CopiedCommit<'a>, Error> {
+    let parent = repo.find_commit(local_parent)?;
+    let (_, parent_uprooted) = tag::retrieve_ripit_tag_or_throw(&parent)?;
+
+    Ok(CopiedCommit::Dropped(SyncedCommit {
+        commit: parent,
+        uprooted: parent_uprooted,
+    }))
 }
 
-/// Cherrypick a given commit on top of HEAD, and add the ripit tag
+/// Returns true if the copy of the commit is its filtered tree
+///
+/// This is the case when every parent of the commit has a copy that is not uprooted, as the
+/// trees of those copies are the filtered trees of the parents, and for root commits copied
+/// as root commits.
+fn is_exact_replay(commit: &git2::Commit, commits_map: &CommitsMap, opts: &app::Options) -> bool {
+    if commit.parent_count() == 0 {
+        return is_copied_as_root(commit, opts);
+    }
+    commit
+        .parent_ids()
+        .all(|id| match commits_map.get(id) {
+            Some(parent) => !parent.uprooted,
+            None => false,
+        })
+}
+
+/// Copy the commit by committing its filtered tree on top of the local parents
+///
+/// Unlike a cherry-pick, this cannot conflict, and neither the index nor the working
+/// directory are used. Only HEAD and the branch are moved, the working directory must be
+/// checked out once the commits are copied.
+fn replay_commit<'a>(
+    repo: &'a git2::Repository,
+    commit: &git2::Commit,
+    local_parents: &[&git2::Commit],
+    commits_map: &CommitsMap,
+    branch: &app::Branch,
+    opts: &app::Options,
+) -> Result<CopiedCommit<'a>, Error> {
+    let source = build_source_commit(repo, commit, opts)?;
+    if let Some(base_id) = find_drop_base(repo, commit, &source, local_parents, commits_map, opts)?
+    {
+        return drop_commit(repo, base_id);
+    }
+
+    let tag = tag::format_ripit_tag(commit, false);
+    let msg = message::update_commit_msg(
+        commit.message().unwrap_or(""),
+        &tag,
+        repo,
+        commits_map,
+        opts,
+    );
+    let new_commit = commit_copy(
+        repo,
+        &source,
+        &msg,
+        &source.tree()?,
+        local_parents,
+        branch,
+        opts,
+    )?;
+    print_created_commit(&new_commit, false, opts);
+
+    Ok(CopiedCommit::Created(SyncedCommit {
+        commit: new_commit,
+        uprooted: false,
+    }))
+}
+
+/// Copy a given commit on top of its local parents, and add the ripit tag
+///
+/// The commit is replayed if possible, and cherry-picked otherwise.
+///
+/// `tip` is the last commit copied on the branch, on which commits without known parents
+/// are uprooted. It is only unset when copying the whole history, before the first root
+/// commit is copied.
 fn copy_commit<'a, 'b>(
     repo: &'a git2::Repository,
     commit: &'b git2::Commit,
     commits_map: &'b CommitsMap,
+    tip: Option<&'b git2::Commit>,
     branch: &app::Branch,
     opts: &app::Options,
-) -> Result<SyncedCommit<'a>, Error> {
-    let head;
-
+) -> Result<CopiedCommit<'a>, Error> {
     if opts.verbose {
-        println!("Copying commit {}...", commit.id());
+        info!(opts, "Copying commit {}...", commit.id());
     }
 
     // Find parent of the commit in local repo
-    let mut local_parents = Vec::new();
-    let mut uprooted = true;
-    let is_merge = commit.parent_count() > 1;
-    for parent_id in commit.parent_ids() {
-        match commits_map.get(parent_id) {
-            Some(parent_ci) => {
-                local_parents.push(&parent_ci.commit);
-                // A commit with uprooted parents is uprooted
-                if !parent_ci.uprooted {
-                    uprooted = false;
-                }
-            }
-            None => {
-                if !opts.uproot {
-                    return Err(Error::UnknownParent {
-                        commit_id: commit.id(),
-                        parent_id,
-                    });
-                }
-            }
-        }
-    }
+    let (mut local_parents, uprooted) = find_local_parents(commit, commits_map, opts)?;
 
-    if local_parents.len() == 0 {
+    if local_parents.is_empty() && !is_copied_as_root(commit, opts) {
         assert!(opts.uproot);
-        // uproot the commit on HEAD
-        // XXX: head *has* a target, because we have at least the bootstrap
-        // commit.
-        head = repo.find_commit(repo.head().unwrap().target().unwrap())?;
-        local_parents.push(&head);
+        // uproot the commit on the last copy
+        local_parents.extend(tip);
     }
 
-    Ok(SyncedCommit {
-        commit: do_cherrypick(
-            repo,
-            commit,
-            &local_parents,
-            is_merge,
-            uprooted,
-            branch,
-            opts,
-        )?,
+    if is_exact_replay(commit, commits_map, opts) {
+        return replay_commit(repo, commit, &local_parents, commits_map, branch, opts);
+    }
+
+    do_cherrypick(
+        repo,
+        commit,
+        &local_parents,
         uprooted,
-    })
+        commits_map,
+        branch,
+        opts,
+    )
 }
 
 /// Sync the local repository with the new changes from the given remote
@@ -357,104 +713,644 @@ pub fn sync_branch_with_remote<'a>(
     repo: &'a git2::Repository,
     branch: &app::Branch,
     commits_map: &mut CommitsMap<'a>,
+    journal: &mut journal::Journal,
     opts: &app::Options,
 ) -> Result<bool, Error> {
-    let local_commit = repo.revparse_single(&branch.name)?.peel_to_commit()?;
-
     // Get the branch last commit in the remote
     let remote_branch = repo.revparse_single(&format!("{}/{}", opts.remote, branch.name))?;
 
+    let local_commit = if branch_exists(repo, branch) {
+        Some(local_tip(repo, branch)?)
+    } else if opts.full_history {
+        info!(opts, "Copying the whole history of branch {}.", branch.name);
+        None
+    } else {
+        let remote_commit = remote_branch.peel_to_commit()?;
+        Some(create_new_branch(
+            repo,
+            branch,
+            &remote_commit,
+            commits_map,
+            opts,
+        )?)
+    };
+
     // Build revwalk from specified commit up to last commit in branch in remote
-    let commits = find_commits_to_sync(
-        &repo,
-        local_commit.id(),
-        &remote_branch,
-        &commits_map,
-        &opts,
-    )?;
+    let commits = match &local_commit {
+        Some(local_commit) => {
+            find_commits_to_sync(repo, local_commit.id(), &remote_branch, commits_map, opts)?
+        }
+        None => find_all_commits_to_sync(repo, &remote_branch, commits_map)?,
+    };
 
-    if commits.len() == 0 {
-        println!(
+    report::emit(
+        &report::Event::Plan {
+            branch: &branch.name,
+            commits: report::plan_commits(&commits, commits_map, opts)?,
+        },
+        opts,
+    );
+    let mut sync_report = report::SyncReport::default();
+
+    if commits.is_empty() {
+        info!(
+            opts,
             "Nothing to synchronize on branch {}, already up to date with {}.",
-            branch.name, opts.remote
+            branch.name,
+            opts.remote
         );
+        if !opts.dry_run {
+            stage_new_branch(repo, branch, local_commit.as_ref())?;
+        }
+        sync_report.emit(branch, &commits, journal, opts);
         return Ok(false);
     }
 
-    print!("Commits to synchronize on {}:\n", branch.name);
+    info!(opts, "Commits to synchronize on {}:", branch.name);
     for ci in &commits {
-        print!(
-            "  Commit {id}\n    {author}\n    {summary}\n\n",
+        info!(
+            opts,
+            "  Commit {id}\n    {author}\n    {summary}\n",
             id = ci.id(),
             author = ci.author(),
             summary = ci.summary().unwrap_or("")
         );
     }
 
-    if !opts.yes && !util::confirm_action() {
-        return Ok(true);
+    if let Err(err) = opts.secrets.check_commits(repo, &commits, opts) {
+        sync_report.failed(&err, None);
+        sync_report.emit(branch, &commits, journal, opts);
+        return Err(err);
+    }
+
+    let res = if opts.dry_run {
+        simulate_sync(
+            repo,
+            &commits,
+            local_commit,
+            commits_map,
+            &mut sync_report,
+            opts,
+        )
+    } else if !opts.yes && !util::confirm_action(opts) {
+        Ok(())
+    } else {
+        copy_commits(
+            repo,
+            &commits,
+            local_commit,
+            commits_map,
+            branch,
+            &mut sync_report,
+            opts,
+        )
+    };
+
+    // report the commits that were not synchronized, and the error that stopped the sync
+    let nb_processed = sync_report.nb_processed();
+    if let Err(err) = &res {
+        sync_report.failed(err, commits.get(nb_processed));
+    }
+    sync_report.emit(branch, &commits[nb_processed..], journal, opts);
+
+    res.map(|_| true)
+}
+
+/// Copy the commits in the local repository, and stage the branch on the last copy
+fn copy_commits<'a>(
+    repo: &'a git2::Repository,
+    commits: &[git2::Commit],
+    local_commit: Option<git2::Commit<'a>>,
+    commits_map: &mut CommitsMap<'a>,
+    branch: &app::Branch,
+    sync_report: &mut report::SyncReport,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    // remove the context of a cherry-pick whose resolution was committed by the user
+    if uses_workdir(repo, opts) {
+        repo.cleanup_state()?;
     }
+    stage_new_branch(repo, branch, local_commit.as_ref())?;
 
-    // cherry-pick every commit, and add the rip-it tag in the commits messages
+    // copy every commit, and add the rip-it tag in the commits messages
     let mut last_commit_id = None;
-    for ci in &commits {
-        let copied_ci = copy_commit(&repo, &ci, &commits_map, branch, opts)?;
+    let mut tip = local_commit;
+    let mut res = Ok(());
+    for ci in commits {
+        let copied_ci = match copy_commit(repo, ci, commits_map, tip.as_ref(), branch, opts) {
+            Ok(copied_ci) => copied_ci,
+            Err(err) => {
+                res = Err(err);
+                break;
+            }
+        };
+        match copied_ci {
+            CopiedCommit::Created(copied_ci) => {
+                // add mapping for this new pair
+                last_commit_id = Some(copied_ci.commit.id());
+                tip = Some(copied_ci.commit.clone());
+                sync_report.created(ci, &copied_ci);
+                commits_map.insert(ci.id(), copied_ci);
+            }
+            CopiedCommit::Dropped(parent_ci) => {
+                info!(
+                    opts,
+                    "Dropped commit {}: all changes are filtered out.",
+                    ci.id()
+                );
+                sync_report.dropped(ci);
+                commits_map.insert_alias(ci.id(), parent_ci);
+            }
+        }
+    }
 
-        // add mapping for this new pair
-        last_commit_id = Some(copied_ci.commit.id());
-        commits_map.insert(ci.id(), copied_ci);
+    // replayed commits only moved HEAD, make the working directory match it, unless a
+    // cherry-pick stopped on conflicts that must be solved by the user
+    if uses_workdir(repo, opts) && repo.state() == git2::RepositoryState::Clean {
+        force_checkout_head(repo)?;
     }
+    res?;
 
-    // Set the branch on the last copied commit
+    // Stage the branch on the last copied commit
     if let Some(ci_id) = last_commit_id {
-        setup_branch(repo, &branch.name, &repo.find_commit(ci_id).unwrap())?;
+        stage_branch(repo, branch, &repo.find_commit(ci_id)?)?;
     }
 
-    Ok(true)
+    Ok(())
 }
 
 // }}}
-// {{{ Bootstrap branch
+// {{{ Continue or abort
 
-/// Cherrypick a given commit on top of HEAD, and add the ripit tag
-fn commit_bootstrap<'a>(
-    repo: &'a git2::Repository,
-    remote_commit: &git2::Commit,
-    remote: &str,
-) -> Result<git2::Commit<'a>, git2::Error> {
-    let msg = format!(
-        "Bootstrap repository from remote {}\n\nrip-it: {}\n",
-        remote,
-        remote_commit.id()
-    );
+/// Open the temporary worktree containing the conflicts, if not in the repository itself
+fn open_worktree(state: &state::SyncState) -> Result<Option<git2::Repository>, Error> {
+    match &state.worktree {
+        Some(path) => Ok(Some(git2::Repository::open(path)?)),
+        None => Ok(None),
+    }
+}
 
-    // commit the whole index
-    let head = match repo.head() {
-        Ok(head) => {
-            let oid = head.target().unwrap();
-            Some(repo.find_commit(oid)?)
-        }
-        Err(_) => None,
+fn is_remote_tip(
+    repo: &git2::Repository,
+    branch: &app::Branch,
+    commit_id: git2::Oid,
+    opts: &app::Options,
+) -> Result<bool, Error> {
+    let remote_branch = repo.revparse_single(&format!("{}/{}", opts.remote, branch.name))?;
+    Ok(remote_branch.peel_to_commit()?.id() == commit_id)
+}
+
+/// Returns true if the commit was made on the parents the copy of the conflicted commit
+/// would have had
+///
+/// The parents of a merge can be in a different order, depending on its mainline.
+fn is_resolution(commit: &git2::Commit, conflict: &state::Conflict) -> bool {
+    let mut parents: Vec<git2::Oid> = commit.parent_ids().collect();
+    let mut copy_parents = conflict.parents.clone();
+    parents.sort();
+    copy_parents.sort();
+    parents == copy_parents
+}
+
+/// Give the resolution committed by the user the identities its copy would have had
+///
+/// The user is the committer of the resolution, and its author as well for merges, as git
+/// then ignores the cherry-picked commit. The resolution is committed again with the
+/// redacted identities of the remote commit, and HEAD is moved on it. This must be done
+/// before the commits are mapped, so that the commit of the user is not mapped.
+pub fn redact_committed_resolution(
+    repo: &git2::Repository,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let state = match state::SyncState::load(repo)? {
+        Some(state) => state,
+        None => return Ok(()),
     };
+    let conflict = match &state.conflict {
+        Some(conflict) => conflict,
+        None => return Ok(()),
+    };
+    let worktree_repo = open_worktree(&state)?;
+    let work_repo = worktree_repo.as_ref().unwrap_or(repo);
+    let head = match work_repo.head().ok().and_then(|head| head.target()) {
+        Some(oid) => repo.find_commit(oid)?,
+        None => return Ok(()),
+    };
+    if !is_resolution(&head, conflict) {
+        return Ok(());
+    }
 
-    let mut parents = vec![];
-    if let Some(h) = head.as_ref() {
-        parents.push(h);
+    let source = build_source_commit(repo, &repo.find_commit(conflict.commit)?, opts)?;
+    if head.author().to_string() == source.author().to_string()
+        && head.committer().to_string() == source.committer().to_string()
+    {
+        return Ok(());
     }
 
-    let sig = repo.signature()?;
-    let commit_oid = repo.commit(
-        Some("HEAD"),
-        &sig,
-        &sig,
-        &msg,
-        &remote_commit.tree()?,
+    let parents: Vec<git2::Commit> = head.parents().collect();
+    let parents: Vec<&git2::Commit> = parents.iter().collect();
+    let oid = repo.commit(
+        None,
+        &source.author(),
+        &source.committer(),
+        head.message().unwrap_or(""),
+        &head.tree()?,
         &parents,
     )?;
+    // the tree is the same, the working directory does not need to be updated
+    work_repo.set_head_detached(oid)?;
+    Ok(())
+}
+
+/// Add the resolution of the conflicts committed by the user in the map
+///
+/// HEAD is detached when solving conflicts, so the commit is only reachable from the HEAD of
+/// the repository, or of the temporary worktree. If it was made on the parents the copy would
+/// have had, the branch is staged on it as it would have been on the copy.
+fn adopt_committed_resolution<'a>(
+    repo: &'a git2::Repository,
+    work_repo: &git2::Repository,
+    conflict: &state::Conflict,
+    commits_map: &mut CommitsMap<'a>,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let head_id = match work_repo.head().ok().and_then(|head| head.target()) {
+        Some(oid) => oid,
+        None => return Ok(()),
+    };
+    commits_map.fill_from_branch(repo, &head_id.to_string())?;
+
+    let head = repo.find_commit(head_id)?;
+    if !is_resolution(&head, conflict) {
+        return Ok(());
+    }
+    let branch = app::Branch::new(conflict.branch.clone());
+    let branch_id = local_tip(repo, &branch)?.id();
+    if conflict.parents.contains(&branch_id) || is_remote_tip(repo, &branch, conflict.commit, opts)?
+    {
+        stage_branch(repo, &branch, &head)?;
+    }
+    Ok(())
+}
+
+/// Pick up the resolution of the conflicts of the last synchronization, if committed by the
+/// user instead of using `--continue`
+///
+/// The context of the cherry-pick is not always cleaned up when committing, so the resolution
+/// is recognized by its parents only.
+pub fn pick_up_committed_resolution<'a>(
+    repo: &'a git2::Repository,
+    commits_map: &mut CommitsMap<'a>,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let state = match state::SyncState::load(repo)? {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let conflict = match &state.conflict {
+        Some(conflict) => conflict,
+        None => return Ok(()),
+    };
+
+    let worktree_repo = open_worktree(&state)?;
+    let work_repo = worktree_repo.as_ref().unwrap_or(repo);
+    adopt_committed_resolution(repo, work_repo, conflict, commits_map, opts)
+}
+
+/// Commit the resolution of the conflicts that stopped the last synchronization
+///
+/// The commit is created with the parents and the message the copy would have had without
+/// conflicts, and is added in the map, so that the synchronization can carry on with the
+/// remaining commits.
+pub fn continue_sync<'a>(
+    repo: &'a git2::Repository,
+    commits_map: &mut CommitsMap<'a>,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let mut state = match state::SyncState::load(repo)? {
+        Some(state) => state,
+        None => return Err(Error::NoConflictToContinue),
+    };
+    let conflict = match state.conflict.take() {
+        Some(conflict) => conflict,
+        None => return Err(Error::NoConflictToContinue),
+    };
+
+    let worktree_repo = open_worktree(&state)?;
+    let work_repo = worktree_repo.as_ref().unwrap_or(repo);
+
+    if work_repo.state() == git2::RepositoryState::Clean {
+        info!(opts, "Resolution of the conflicts already committed.");
+        return adopt_committed_resolution(repo, work_repo, &conflict, commits_map, opts);
+    }
+    if work_repo.index()?.has_conflicts() {
+        return Err(Error::UnresolvedConflicts);
+    }
+    // the working directory is reset when committing, changes not in the index would be lost
+    if work_repo
+        .diff_index_to_workdir(None, None)?
+        .deltas()
+        .count()
+        > 0
+    {
+        return Err(Error::HasLocalChanges);
+    }
+
+    let branch = app::Branch::new(conflict.branch);
+    let commit = repo.find_commit(conflict.commit)?;
+    let mut parents = Vec::new();
+    for parent_id in &conflict.parents {
+        parents.push(repo.find_commit(*parent_id)?);
+    }
+    let local_parents: Vec<&git2::Commit> = parents.iter().collect();
+
+    let source = build_source_commit(repo, &commit, opts)?;
+    let tag = tag::format_ripit_tag(&commit, conflict.uprooted);
+    let msg = message::update_commit_msg(
+        commit.message().unwrap_or(""),
+        &tag,
+        repo,
+        commits_map,
+        opts,
+    );
+
+    let tree = repo.find_tree(work_repo.index()?.write_tree()?)?;
+    let new_commit = commit_copy(repo, &source, &msg, &tree, &local_parents, &branch, opts)?;
+    print_created_commit(&new_commit, conflict.uprooted, opts);
+
+    // the resolution is committed, the temporary worktree is no longer needed
+    if state.worktree.is_some() {
+        state.remove_worktree(repo)?;
+        state.save(repo)?;
+    } else {
+        force_checkout_head(repo)?;
+        repo.cleanup_state()?;
+    }
+
+    // if it was the last commit to synchronize, stage the branch on its copy
+    if is_remote_tip(repo, &branch, commit.id(), opts)? {
+        stage_branch(repo, &branch, &new_commit)?;
+    }
+
+    commits_map.insert(
+        commit.id(),
+        SyncedCommit {
+            commit: new_commit,
+            uprooted: conflict.uprooted,
+        },
+    );
+    Ok(())
+}
+
+/// Restore the repository as it was before the synchronization in progress
+pub fn abort_sync(repo: &git2::Repository, opts: &app::Options) -> Result<(), Error> {
+    let mut state = match state::SyncState::load(repo)? {
+        Some(state) => state,
+        None => return Err(Error::NoSyncToAbort),
+    };
+
+    state.restore(repo)?;
+    state::SyncState::end(repo)?;
+    info!(opts, "Synchronization aborted.");
+    Ok(())
+}
+
+/// Put the branches back where they were before the last finished synchronization
+pub fn rollback_sync(repo: &git2::Repository, opts: &app::Options) -> Result<(), Error> {
+    if state::SyncState::load(repo)?.is_some() {
+        return Err(Error::SyncInProgress);
+    }
+    let mut state = match state::SyncState::load_last(repo)? {
+        Some(state) => state,
+        None => return Err(Error::NoSyncToRollback),
+    };
+
+    // the commits made on the branches since would be lost
+    state.check_synced_branches(repo)?;
+    state.restore(repo)?;
+    state::SyncState::forget_last(repo)?;
+    info!(opts, "Synchronization rolled back.");
+    Ok(())
+}
+
+// }}}
+// {{{ Dry run
+
+/// Compute the copy of a commit, without modifying HEAD, the index or the branches
+///
+/// The commit is replayed, or cherry-picked in memory, and its copy is created as a dangling
+/// commit, so that the commits depending on it can be simulated as well. As the copy is built
+/// exactly as during a synchronization, it has the id the real copy would have.
+///
+/// None is returned if the cherry-pick causes conflicts.
+fn simulate_copy<'a>(
+    repo: &'a git2::Repository,
+    commit: &git2::Commit,
+    commits_map: &CommitsMap,
+    tip: Option<&git2::Commit>,
+    opts: &app::Options,
+) -> Result<Option<CopiedCommit<'a>>, Error> {
+    let (mut local_parents, uprooted) = find_local_parents(commit, commits_map, opts)?;
+    if local_parents.is_empty() && !is_copied_as_root(commit, opts) {
+        assert!(opts.uproot);
+        // uproot the commit on the last created commit, as HEAD would be
+        local_parents.extend(tip);
+    }
+
+    let source = build_source_commit(repo, commit, opts)?;
+    if let Some(base_id) = find_drop_base(repo, commit, &source, &local_parents, commits_map, opts)?
+    {
+        return Ok(Some(drop_commit(repo, base_id)?));
+    }
+
+    let tree = if is_exact_replay(commit, commits_map, opts) {
+        source.tree()?
+    } else {
+        let mainline = find_mainline(commit, commits_map, opts)?;
+        let base = repo.find_commit(find_base_commit(
+            commit,
+            mainline,
+            &local_parents,
+            commits_map,
+        ))?;
+        let mut index = repo.cherrypick_commit(&source, &base, mainline as u32, None)?;
+        if index.has_conflicts() {
+            return Ok(None);
+        }
+        repo.find_tree(index.write_tree_to(repo)?)?
+    };
+
+    let tag = tag::format_ripit_tag(commit, uprooted);
+    let msg = message::update_commit_msg(
+        commit.message().unwrap_or(""),
+        &tag,
+        repo,
+        commits_map,
+        opts,
+    );
+    let oid = repo.commit(
+        None,
+        &source.author(),
+        &source.committer(),
+        &msg,
+        &tree,
+        &local_parents,
+    )?;
+
+    Ok(Some(CopiedCommit::Created(SyncedCommit {
+        commit: repo.find_commit(oid)?,
+        uprooted,
+    })))
+}
+
+/// Print the commit that would be created, with the files it modifies
+fn print_simulated_commit(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    copy: &git2::Commit,
+    opts: &app::Options,
+) -> Result<(), git2::Error> {
+    info!(
+        opts,
+        "Commit {} would be copied as {}:",
+        commit.id(),
+        copy.id()
+    );
+    info!(opts, "  Author: {}", copy.author());
+    info!(opts, "  Committer: {}", copy.committer());
+    let parents: Vec<String> = copy.parent_ids().map(|id| id.to_string()).collect();
+    info!(opts, "  Parents: {}", parents.join(" "));
+
+    info!(opts);
+    for line in copy.message().unwrap_or("").lines() {
+        info!(opts, "    {}", line);
+    }
+    info!(opts);
+
+    // root copies are compared to the empty tree
+    let parent_tree = match copy.parent_count() {
+        0 => None,
+        _ => Some(copy.parent(0)?.tree()?),
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&copy.tree()?), None)?;
+    for delta in diff.deltas() {
+        let status = match delta.status() {
+            git2::Delta::Added => 'A',
+            git2::Delta::Deleted => 'D',
+            git2::Delta::Renamed => 'R',
+            git2::Delta::Copied => 'C',
+            git2::Delta::Typechange => 'T',
+            _ => 'M',
+        };
+        let path = delta.new_file().path().or_else(|| delta.old_file().path());
+        let path = path.map(|p| p.display().to_string()).unwrap_or_default();
+        info!(opts, "  {} {}", status, path);
+    }
+    info!(opts);
+
+    Ok(())
+}
+
+/// Print the commits that would be created by the synchronization, without creating them
+///
+/// The mappings of the simulated commits are only added in memory.
+fn simulate_sync<'a>(
+    repo: &'a git2::Repository,
+    commits: &[git2::Commit],
+    local_commit: Option<git2::Commit<'a>>,
+    commits_map: &mut CommitsMap<'a>,
+    sync_report: &mut report::SyncReport,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let mut tip = local_commit;
+
+    for ci in commits {
+        match simulate_copy(repo, ci, commits_map, tip.as_ref(), opts)? {
+            Some(CopiedCommit::Created(copied_ci)) => {
+                print_simulated_commit(repo, ci, &copied_ci.commit, opts)?;
+                tip = Some(repo.find_commit(copied_ci.commit.id())?);
+                sync_report.created(ci, &copied_ci);
+                commits_map.insert(ci.id(), copied_ci);
+            }
+            Some(CopiedCommit::Dropped(parent_ci)) => {
+                info!(
+                    opts,
+                    "Commit {} would be dropped: all changes are filtered out.\n",
+                    ci.id()
+                );
+                sync_report.dropped(ci);
+                commits_map.insert_alias(ci.id(), parent_ci);
+            }
+            None => {
+                info!(
+                    opts,
+                    "Commit {} would cause conflicts, the next commits cannot be simulated.",
+                    ci.id()
+                );
+                let err = Error::HasConflicts {
+                    summary: ci.summary().unwrap_or("").to_owned(),
+                    worktree: None,
+                };
+                sync_report.failed(&err, Some(ci));
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// }}}
+// {{{ Bootstrap branch
 
+/// Cherrypick a given commit on top of HEAD, and add the ripit tag
+fn commit_bootstrap<'a>(
+    repo: &'a git2::Repository,
+    branch: &str,
+    remote_commit: &git2::Commit,
+    opts: &app::Options,
+) -> Result<git2::Commit<'a>, git2::Error> {
+    // commit the whole index
+    let head = match repo.head() {
+        Ok(head) => {
+            let oid = head.target().unwrap();
+            Some(repo.find_commit(oid)?)
+        }
+        Err(_) => None,
+    };
+
+    let commit = create_bootstrap_commit(repo, branch, remote_commit, "HEAD", head.as_ref(), opts)?;
     force_checkout_head(repo)?;
+    Ok(commit)
+}
 
-    Ok(repo.find_commit(commit_oid)?)
+/// Create a commit with the filtered tree of the remote commit, tagged with it
+///
+/// The reference is updated to point to the new commit. Its identities, date and message
+/// follow the configured template.
+fn create_bootstrap_commit<'a>(
+    repo: &'a git2::Repository,
+    branch: &str,
+    remote_commit: &git2::Commit,
+    update_ref: &str,
+    parent: Option<&git2::Commit>,
+    opts: &app::Options,
+) -> Result<git2::Commit<'a>, git2::Error> {
+    let template = &opts.bootstrap_template;
+    let msg = template.message(&opts.remote, branch, remote_commit.id());
+
+    let parents: Vec<&git2::Commit> = parent.into_iter().collect();
+    let (author, committer) = template.signatures(repo, &opts.identities)?;
+    let commit_oid = repo.commit(
+        Some(update_ref),
+        &author,
+        &committer,
+        &msg,
+        &opts.tree_filter.filter_tree(repo, &remote_commit.tree()?)?,
+        &parents,
+    )?;
+
+    repo.find_commit(commit_oid)
 }
 
 /// Returns whether HEAD is currently tracking the given branch
@@ -470,54 +1366,205 @@ fn head_is_branch(repo: &git2::Repository, branch: &str) -> Result<bool, git2::E
     })
 }
 
+/// Returns whether the branch exists in the local repository, or is being created
+pub fn branch_exists(repo: &git2::Repository, branch: &app::Branch) -> bool {
+    repo.find_reference(&branch.refname).is_ok()
+        || repo.find_reference(&branch.staging_refname()).is_ok()
+}
+
+/// Returns the last commit of the branch, or of its staged copies if it is being synchronized
+pub fn local_tip<'a>(
+    repo: &'a git2::Repository,
+    branch: &app::Branch,
+) -> Result<git2::Commit<'a>, git2::Error> {
+    match repo.find_reference(&branch.staging_refname()) {
+        Ok(reference) => reference.peel_to_commit(),
+        Err(_) => repo.revparse_single(&branch.name)?.peel_to_commit(),
+    }
+}
+
+/// Set the staging reference of the branch to this commit
+fn stage_branch(
+    repo: &git2::Repository,
+    branch: &app::Branch,
+    commit: &git2::Commit,
+) -> Result<(), git2::Error> {
+    let log_msg = format!("ripit: {}", commit.summary().unwrap_or(""));
+    repo.reference(&branch.staging_refname(), commit.id(), true, &log_msg)?;
+    Ok(())
+}
+
+/// Move the branches on their staged copies, once every branch is synchronized
+///
+/// Every branch is only moved if it still points to the commit it pointed to when the
+/// synchronization started, and only created if it still does not exist, otherwise the
+/// branches already moved are put back.
+pub fn apply_staged_branches(
+    repo: &git2::Repository,
+    state: &state::SyncState,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let mut staged = Vec::new();
+    for branch in &opts.branches {
+        if let Ok(oid) = repo.refname_to_id(&branch.staging_refname()) {
+            let old_id = match state.branch_id(&branch.refname) {
+                Some(old_id) => Some(old_id),
+                None if state.is_new_branch(&branch.refname) => None,
+                None => Some(repo.refname_to_id(&branch.refname)?),
+            };
+            staged.push((branch, old_id, oid));
+        }
+    }
+
+    for (i, (branch, old_id, new_id)) in staged.iter().enumerate() {
+        let res = match old_id {
+            Some(old_id) => repo.reference_matching(
+                &branch.refname,
+                *new_id,
+                true,
+                *old_id,
+                "ripit: synchronize",
+            ),
+            None => repo.reference(&branch.refname, *new_id, false, "ripit: create branch"),
+        };
+        if let Err(err) = res {
+            for (branch, old_id, _) in &staged[..i] {
+                match old_id {
+                    Some(old_id) => {
+                        repo.reference(&branch.refname, *old_id, true, "ripit: rollback")?;
+                    }
+                    None => repo.find_reference(&branch.refname)?.delete()?,
+                }
+            }
+            return Err(err.into());
+        }
+    }
+
+    for (branch, _, _) in &staged {
+        repo.find_reference(&branch.staging_refname())?.delete()?;
+    }
+    Ok(())
+}
+
 /// Create or set the branch to this commit
 fn setup_branch(
     repo: &git2::Repository,
     branch: &str,
     commit: &git2::Commit,
 ) -> Result<(), git2::Error> {
-    if !head_is_branch(&repo, branch)? {
-        repo.branch(branch, &commit, true)?;
+    if !head_is_branch(repo, branch)? {
+        repo.branch(branch, commit, true)?;
     }
     Ok(())
 }
 
+/// Find the commit of the remote branch to bootstrap from
+///
+/// `at` is either a date with the format YYYY-MM-DD, to bootstrap from the first commit made
+/// on or after it, following the first parents of the branch, or a revision contained in
+/// the branch.
+fn find_bootstrap_commit<'a>(
+    repo: &'a git2::Repository,
+    branch: &app::Branch,
+    tip: git2::Commit<'a>,
+    at: &str,
+) -> Result<git2::Commit<'a>, Error> {
+    if let Some(date) = util::parse_date(at) {
+        let mut found = None;
+        let mut commit = tip;
+        while commit.committer().when().seconds() >= date {
+            let parent = commit.parent(0).ok();
+            found = Some(commit);
+            match parent {
+                Some(parent) => commit = parent,
+                None => break,
+            }
+        }
+        return found.ok_or_else(|| Error::NoCommitSince {
+            date: at.to_owned(),
+            branch: branch.name.clone(),
+        });
+    }
+
+    let commit = match repo
+        .revparse_single(at)
+        .and_then(|obj| obj.peel_to_commit())
+    {
+        Ok(commit) => commit,
+        Err(_) => return Err(Error::UnknownRevision { rev: at.to_owned() }),
+    };
+    if commit.id() != tip.id() && !repo.graph_descendant_of(tip.id(), commit.id())? {
+        return Err(Error::RevisionNotInBranch {
+            rev: at.to_owned(),
+            branch: branch.name.clone(),
+        });
+    }
+    Ok(commit)
+}
+
 /// Bootstrap the branch in the local repo with the state of the branch in the remote repo
 ///
-/// Create a commit that will contain the whole index of the remote's branch HEAD, with the
-/// appropriate ripit tag.
+/// Create a commit that will contain the whole index of the remote's branch HEAD, or of the
+/// commit given with --bootstrap-at, with the appropriate ripit tag.
 /// Following this bootstrap, synchronisation between the two repos will be possible.
 pub fn bootstrap_branch_with_remote<'a>(
     repo: &'a git2::Repository,
     branch: &app::Branch,
     commits_map: &mut CommitsMap<'a>,
+    journal: &mut journal::Journal,
     opts: &app::Options,
 ) -> Result<(), Error> {
     // Get the branch last commit in the remote
     let remote_branch = repo.revparse_single(&format!("{}/{}", opts.remote, branch.name))?;
-    let remote_commit = remote_branch.peel_to_commit()?;
+    let remote_commit = match &opts.bootstrap_at {
+        Some(at) => find_bootstrap_commit(repo, branch, remote_branch.peel_to_commit()?, at)?,
+        None => remote_branch.peel_to_commit()?,
+    };
+
+    // a new branch forking from a synchronized commit is created on its copy
+    let fork_point = if branch_exists(repo, branch) {
+        None
+    } else {
+        find_fork_point(repo, &remote_commit, commits_map)?
+    };
 
-    match commits_map.get(remote_commit.id()) {
-        Some(ci) => {
+    let (commit_id, public_id) = match (commits_map.get(remote_commit.id()), fork_point) {
+        (Some(ci), _) => {
             // If the commit exists in the CommitsMap, it means it was created
             // when boostrapping another branch: we can re-use this commit.
-            println!(
+            info!(
+                opts,
                 "Re-use commit {} to bootstrap branch {}.",
                 ci.commit.id(),
                 branch.name
             );
             setup_branch(repo, &branch.name, &ci.commit)?;
+            (remote_commit.id(), ci.commit.id())
         }
-        None => {
+        (None, Some((fork_id, local_commit))) => {
+            info!(
+                opts,
+                "Branch {} forks from synchronized commit {}, created on {}. The next \
+                 synchronization copies the commits after the fork.",
+                branch.name,
+                fork_id,
+                local_commit.id()
+            );
+            setup_branch(repo, &branch.name, &local_commit)?;
+            (fork_id, local_commit.id())
+        }
+        (None, None) => {
             // build the bootstrap commit from the state of this commit
-            let commit = commit_bootstrap(&repo, &remote_commit, &opts.remote)?;
-            println!(
+            let commit = commit_bootstrap(repo, &branch.name, &remote_commit, opts)?;
+            info!(
+                opts,
                 "Bootstrap commit {} created for branch {}.",
                 commit.id(),
                 branch.name
             );
 
             setup_branch(repo, &branch.name, &commit)?;
+            let public_id = commit.id();
             commits_map.insert(
                 remote_commit.id(),
                 SyncedCommit {
@@ -525,10 +1572,157 @@ pub fn bootstrap_branch_with_remote<'a>(
                     uprooted: false,
                 },
             );
+            (remote_commit.id(), public_id)
         }
     };
 
+    report::emit(
+        &report::Event::Bootstrap {
+            branch: &branch.name,
+            commit: commit_id.to_string(),
+            public_id: public_id.to_string(),
+        },
+        opts,
+    );
+    journal.record(report::BranchResult::bootstrap(
+        branch, commit_id, public_id,
+    ));
+    Ok(())
+}
+
+/// Append a bootstrap commit on a branch, with the state of the branch in the remote repo
+///
+/// Unlike the bootstrap, the history of the local branch is kept: the commit is created on
+/// top of it, so that the next synchronizations only copy the remote commits made after it.
+/// The remote commits not yet synchronized are thus never copied.
+pub fn rebootstrap_branch<'a>(
+    repo: &'a git2::Repository,
+    branch: &app::Branch,
+    commits_map: &mut CommitsMap<'a>,
+    journal: &mut journal::Journal,
+    opts: &app::Options,
+) -> Result<(), Error> {
+    let local_commit = match repo.find_reference(&branch.refname) {
+        Ok(reference) => reference.peel_to_commit()?,
+        Err(_) => {
+            return Err(Error::BranchNotBootstrapped {
+                branch: branch.name.clone(),
+            })
+        }
+    };
+    let remote_branch = repo.revparse_single(&format!("{}/{}", opts.remote, branch.name))?;
+    let remote_commit = remote_branch.peel_to_commit()?;
+
+    if let Some(synced) = commits_map.get(remote_commit.id()) {
+        info!(
+            opts,
+            "Commit {} of branch {} is already synchronized as {}, nothing to bootstrap.",
+            remote_commit.id(),
+            branch.name,
+            synced.commit.id()
+        );
+        return Ok(());
+    }
+
+    let commit = create_bootstrap_commit(
+        repo,
+        &branch.name,
+        &remote_commit,
+        &branch.refname,
+        Some(&local_commit),
+        opts,
+    )?;
+    info!(
+        opts,
+        "Bootstrap commit {} appended on branch {}.",
+        commit.id(),
+        branch.name
+    );
+
+    let public_id = commit.id();
+    commits_map.insert(
+        remote_commit.id(),
+        SyncedCommit {
+            commit,
+            uprooted: false,
+        },
+    );
+
+    report::emit(
+        &report::Event::Bootstrap {
+            branch: &branch.name,
+            commit: remote_commit.id().to_string(),
+            public_id: public_id.to_string(),
+        },
+        opts,
+    );
+    journal.record(report::BranchResult::bootstrap(
+        branch,
+        remote_commit.id(),
+        public_id,
+    ));
     Ok(())
 }
 
+/// Find the last synchronized commit of the remote branch, and the local commit it is mapped on
+pub fn find_fork_point<'a>(
+    repo: &'a git2::Repository,
+    remote_commit: &git2::Commit,
+    commits_map: &CommitsMap,
+) -> Result<Option<(git2::Oid, git2::Commit<'a>)>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL);
+    revwalk.push(remote_commit.id())?;
+    for oid in revwalk {
+        let oid = oid?;
+        if let Some(synced) = commits_map.get(oid) {
+            return Ok(Some((oid, repo.find_commit(synced.commit.id())?)));
+        }
+    }
+    Ok(None)
+}
+
+/// Create a branch to synchronize that does not exist in the local repository
+///
+/// The remote branch must fork from a synchronized commit: the local commit it is mapped on is
+/// returned, so that only the commits after the fork are synchronized. The branch is only
+/// staged on it once the synchronization is confirmed.
+fn create_new_branch<'a>(
+    repo: &'a git2::Repository,
+    branch: &app::Branch,
+    remote_commit: &git2::Commit,
+    commits_map: &CommitsMap,
+    opts: &app::Options,
+) -> Result<git2::Commit<'a>, Error> {
+    let (fork_id, local_commit) = match find_fork_point(repo, remote_commit, commits_map)? {
+        Some(fork_point) => fork_point,
+        None => {
+            return Err(Error::BranchNotBootstrapped {
+                branch: branch.name.clone(),
+            })
+        }
+    };
+
+    info!(
+        opts,
+        "Branch {} forks from synchronized commit {}, it starts from {}.",
+        branch.name,
+        fork_id,
+        local_commit.id()
+    );
+    Ok(local_commit)
+}
+
+/// Stage a branch that does not exist yet on the commit it forks from
+fn stage_new_branch(
+    repo: &git2::Repository,
+    branch: &app::Branch,
+    fork_commit: Option<&git2::Commit>,
+) -> Result<(), git2::Error> {
+    match fork_commit {
+        Some(commit) if !branch_exists(repo, branch) => stage_branch(repo, branch, commit),
+        _ => Ok(()),
+    }
+}
+
 // }}}
@@ -1,22 +1,43 @@
+use crate::app;
 // for stdout().flush
 use std::io::Write;
 
+/// Print a message for the user
+///
+/// With the JSON output, the messages are printed on stderr, so that stdout only contains
+/// the JSON events.
+macro_rules! info {
+    ($opts:expr) => {
+        info!($opts, "")
+    };
+    ($opts:expr, $($arg:tt)*) => {
+        if $opts.output == crate::app::Output::Json {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
 /// Display a prompt asking for confirmation by the user
 ///
 /// Returns true if the user confirmed, false in all other cases
-pub fn confirm_action() -> bool {
+pub fn confirm_action(opts: &app::Options) -> bool {
     let mut input = String::new();
 
     loop {
-        print!("Is this ok? [yN] ");
-        std::io::stdout().flush().unwrap();
+        if opts.output == app::Output::Json {
+            eprint!("Is this ok? [yN] ");
+        } else {
+            print!("Is this ok? [yN] ");
+            std::io::stdout().flush().unwrap();
+        }
 
-        match std::io::stdin().read_line(&mut input) {
-            Err(_) => return false,
-            _ => (),
-        };
+        if std::io::stdin().read_line(&mut input).is_err() {
+            return false;
+        }
 
-        match input.trim().as_ref() {
+        match input.trim() {
             "y" | "Y" => return true,
             "n" | "N" => return false,
             _ => (),
@@ -24,3 +45,99 @@ pub fn confirm_action() -> bool {
         input.clear();
     }
 }
+
+/// Returns the quotient and the remainder of the division, rounding the quotient down, so
+/// that the remainder is never negative
+fn floor_div(a: i64, b: i64) -> (i64, i64) {
+    let (quotient, remainder) = (a / b, a % b);
+    if remainder < 0 {
+        (quotient - 1, remainder + b)
+    } else {
+        (quotient, remainder)
+    }
+}
+
+/// Returns the number of days between 1970-01-01 and the date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = floor_div(year, 400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the date of the day, counted from 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let (era, day_of_era) = floor_div(days, 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format the time in UTC, as in RFC 3339: YYYY-MM-DDTHH:MM:SSZ
+pub fn format_utc(time: std::time::SystemTime) -> String {
+    let secs = match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    let (days, secs) = floor_div(secs, 86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parse a date with the format YYYY-MM-DD, and returns the timestamp of its start, in UTC
+pub fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if date.len() != 10 {
+        return None;
+    }
+    // invalid months and days are not found back from the computed day
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    Some(days * 86400)
+}
+
+/// Parse a time with the format YYYY-MM-DDTHH:MM:SSZ, or a date with the format YYYY-MM-DD,
+/// and returns its timestamp, in UTC
+pub fn parse_utc(time: &str) -> Option<i64> {
+    // the parts are sliced by bytes
+    if !time.is_ascii() {
+        return None;
+    }
+    if time.len() == 10 {
+        return parse_date(time);
+    }
+    if time.len() != 20 || &time[10..11] != "T" || !time.ends_with('Z') {
+        return None;
+    }
+
+    let mut parts = time[11..19].splitn(3, ':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: i64 = parts.next()?.parse().ok()?;
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    Some(parse_date(&time[..10])? + hours * 3600 + minutes * 60 + seconds)
+}
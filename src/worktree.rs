@@ -0,0 +1,74 @@
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Directory containing the administrative files of a linked worktree
+fn admin_dir(repo: &git2::Repository, path: &Path) -> PathBuf {
+    // the worktree is named after its directory, which is unique
+    repo.path()
+        .join("worktrees")
+        .join(path.file_name().unwrap_or_default())
+}
+
+fn write_file(path: &Path, content: &str) -> Result<(), Error> {
+    match std::fs::write(path, content) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(Error::WorktreeError {
+            err,
+            path: path.to_owned(),
+        }),
+    }
+}
+
+/// Create a linked worktree in a temporary directory, with HEAD detached on the commit
+///
+/// The worktree is created as `git worktree add --detach` would, so that the user can use
+/// it as any other worktree. It is not removed automatically, see `remove`.
+pub fn add(repo: &git2::Repository, commit: git2::Oid) -> Result<PathBuf, Error> {
+    let path = match tempfile::Builder::new().prefix("ripit-").tempdir() {
+        Ok(dir) => dir.into_path(),
+        Err(err) => {
+            return Err(Error::WorktreeError {
+                err,
+                path: std::env::temp_dir(),
+            })
+        }
+    };
+
+    let admin = admin_dir(repo, &path);
+    if let Err(err) = std::fs::create_dir_all(&admin) {
+        return Err(Error::WorktreeError { err, path: admin });
+    }
+    write_file(
+        &admin.join("gitdir"),
+        &format!("{}\n", path.join(".git").display()),
+    )?;
+    write_file(&admin.join("commondir"), "../..\n")?;
+    write_file(&admin.join("HEAD"), &format!("{}\n", commit))?;
+    write_file(
+        &path.join(".git"),
+        &format!("gitdir: {}\n", admin.display()),
+    )?;
+
+    // populate the index and the working directory
+    let worktree = git2::Repository::open(&path)?;
+    let mut checkout_opts = git2::build::CheckoutBuilder::new();
+    checkout_opts.force();
+    worktree.checkout_head(Some(&mut checkout_opts))?;
+
+    Ok(path)
+}
+
+/// Remove a worktree created with `add`
+pub fn remove(repo: &git2::Repository, path: &Path) -> Result<(), Error> {
+    for dir in &[path.to_owned(), admin_dir(repo, path)] {
+        if let Err(err) = std::fs::remove_dir_all(dir) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(Error::WorktreeError {
+                    err,
+                    path: dir.to_owned(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -12,9 +12,9 @@ pub fn _pause() {
     use std::io::Read;
 
     let mut stdout = std::io::stdout();
-    stdout.write(b"Press Enter to continue...").unwrap();
+    stdout.write_all(b"Press Enter to continue...").unwrap();
     stdout.flush().unwrap();
-    std::io::stdin().read(&mut [0]).unwrap();
+    std::io::stdin().read_exact(&mut [0]).unwrap();
 }
 
 // {{{ ripit exec handling
@@ -35,7 +35,7 @@ fn find_ripit_exec() -> PathBuf {
 // }}}
 // {{{ Test repo
 
-pub struct TestRepo(git2::Repository);
+pub struct TestRepo(pub git2::Repository);
 
 impl Deref for TestRepo {
     type Target = git2::Repository;
@@ -51,7 +51,7 @@ impl TestRepo {
         self.checkout_head(Some(&mut opts.force())).unwrap();
     }
 
-    pub fn do_commit(&self, msg: &str) -> git2::Commit {
+    pub fn do_commit(&self, msg: &str) -> git2::Commit<'_> {
         let mut index = self.index().unwrap();
         let tree = self.find_tree(index.write_tree().unwrap()).unwrap();
 
@@ -72,8 +72,9 @@ impl TestRepo {
         self.find_commit(commit_oid).unwrap()
     }
 
-    fn write_and_add_file(&self, filename: &str, content: &str) {
+    pub fn write_and_add_file(&self, filename: &str, content: &str) {
         let path = Path::new(self.workdir().unwrap()).join(filename);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
         fs::File::create(&path)
             .unwrap()
             .write_all(content.as_bytes())
@@ -83,14 +84,19 @@ impl TestRepo {
         index.add_path(Path::new(filename)).unwrap();
     }
 
-    pub fn commit_file(&self, filename: &str, commit_msg: &str) -> git2::Commit {
+    pub fn commit_file(&self, filename: &str, commit_msg: &str) -> git2::Commit<'_> {
         self.write_and_add_file(filename, commit_msg);
         self.do_commit(commit_msg)
     }
 
-    pub fn resolve_conflict_and_commit(&self, filename: &str) -> git2::Commit {
-        // overwrite file containing conflicts, and add it to the index
+    /// Overwrite file containing conflicts, and add it to the index
+    pub fn resolve_conflict(&self, filename: &str) {
         self.write_and_add_file(filename, "resolved conflict");
+        self.index().unwrap().write().unwrap();
+    }
+
+    pub fn resolve_conflict_and_commit(&self, filename: &str) -> git2::Commit<'_> {
+        self.resolve_conflict(filename);
 
         // do a commit, but get the commit msg from the .git/MERGE_MSG file.
         // This is to simulate what "git commit" would do
@@ -114,7 +120,7 @@ impl TestRepo {
     }
 
     /// Commit a file, and tag the commit (the tag name and the files content are the same)
-    fn commit_file_and_tag(&self, filename: &str, tag: &str) -> git2::Commit {
+    fn commit_file_and_tag(&self, filename: &str, tag: &str) -> git2::Commit<'_> {
         let ci = self.commit_file(filename, &format!("{}\n\nline test filtered\ndetails", tag));
         self.tag_lightweight(tag, ci.as_object(), true).unwrap();
         ci
@@ -129,6 +135,11 @@ impl TestRepo {
         assert_eq!(index_elem.is_some(), file_in_index);
     }
 
+    pub fn check_file_content(&self, filename: &str, content: &str) {
+        let path = Path::new(self.workdir().unwrap()).join(filename);
+        assert_eq!(fs::read_to_string(path).unwrap(), content);
+    }
+
     pub fn count_commits(&self) -> usize {
         let mut revwalk = self.revwalk().unwrap();
         revwalk.push_head().unwrap();
@@ -137,7 +148,7 @@ impl TestRepo {
     }
 
     /// Do a commit-merge of the given commit in HEAD
-    fn do_merge_commit(&self, theirs: &git2::Commit, content: &str) -> git2::Commit {
+    pub fn do_merge_commit(&self, theirs: &git2::Commit, content: &str) -> git2::Commit<'_> {
         let annotated_theirs = self.find_annotated_commit(theirs.id()).unwrap();
         self.merge(&[&annotated_theirs], None, None).unwrap();
 
@@ -180,7 +191,7 @@ impl TestRepo {
                 &sig,
                 content,
                 &tree,
-                &[&head_ci, &theirs],
+                &[&head_ci, theirs],
             )
             .unwrap();
         let ci = self.find_commit(commit_oid).unwrap();
@@ -189,7 +200,7 @@ impl TestRepo {
         ci
     }
 
-    fn do_merge(&self, theirs: &git2::Commit, content: &str) -> git2::Commit {
+    fn do_merge(&self, theirs: &git2::Commit, content: &str) -> git2::Commit<'_> {
         let ci = self.do_merge_commit(theirs, content);
         self.tag_lightweight(content, ci.as_object(), true).unwrap();
         ci
@@ -234,7 +245,7 @@ impl TestEnv {
 
         // Setup remote repo as remote named "private" of local repo
         let url = remote_dir.path().to_str().unwrap();
-        local_repo.remote("private", &url).unwrap();
+        local_repo.remote("private", url).unwrap();
 
         for repo in &[&remote_repo, &local_repo] {
             // Set up default cfg
@@ -415,7 +426,22 @@ filters:
         self.remote_repo.do_merge(&c4, "c6");
     }
 
-    fn run_ripit(&self, successful: bool, args: &[&str], err_msg: Option<&str>) {
+    /// Append lines to the configuration file
+    pub fn add_cfg(&self, cfg: &str) {
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&self.cfg_path)
+            .unwrap();
+        file.write_all(cfg.as_bytes()).unwrap();
+    }
+
+    fn run_ripit(
+        &self,
+        successful: bool,
+        args: &[&str],
+        err_msg: Option<&str>,
+        input: Option<&str>,
+    ) -> String {
         let mut args = args.to_vec();
         args.push(&self.cfg_path);
 
@@ -423,8 +449,21 @@ filters:
         cmd.current_dir(self.local_dir.path());
         cmd.args(args);
 
-        let output = cmd.output().expect("ripit command");
-        println!("stdout: {}", str::from_utf8(&output.stdout).unwrap());
+        let output = match input {
+            Some(input) => {
+                cmd.stdin(process::Stdio::piped());
+                cmd.stdout(process::Stdio::piped());
+                cmd.stderr(process::Stdio::piped());
+                let mut child = cmd.spawn().expect("ripit command");
+                let mut stdin = child.stdin.take().unwrap();
+                stdin.write_all(input.as_bytes()).unwrap();
+                drop(stdin);
+                child.wait_with_output().expect("ripit command")
+            }
+            None => cmd.output().expect("ripit command"),
+        };
+        let stdout = str::from_utf8(&output.stdout).unwrap();
+        println!("stdout: {}", stdout);
 
         let stderr = str::from_utf8(&output.stderr).unwrap();
         if let Some(msg) = err_msg {
@@ -438,20 +477,31 @@ filters:
         // repo
         self.local_repo.index().unwrap().read(true).unwrap();
         self.remote_repo.index().unwrap().read(true).unwrap();
+
+        stdout.to_owned()
     }
 
-    pub fn run_ripit_failure(&self, args: &[&str], err_msg: Option<&str>) {
-        self.run_ripit(false, args, err_msg)
+    /// Run ripit, expecting a failure, and returns its standard output
+    pub fn run_ripit_failure(&self, args: &[&str], err_msg: Option<&str>) -> String {
+        self.run_ripit(false, args, err_msg, None)
     }
 
-    pub fn run_ripit_success(&self, args: &[&str]) {
-        self.run_ripit(true, args, None);
+    /// Run ripit, and returns its standard output
+    pub fn run_ripit_success(&self, args: &[&str]) -> String {
+        self.run_ripit_answering(args, None)
+    }
+
+    /// Run ripit, answering its questions with the input, and returns its standard output
+    pub fn run_ripit_answering(&self, args: &[&str], input: Option<&str>) -> String {
+        let stdout = self.run_ripit(true, args, None, input);
 
         // make sure that the git context is cleaned
         let path = self.local_repo.path().join("CHERRY_PICK_HEAD");
         assert!(!path.exists());
         let path = self.local_repo.path().join("MERGE_HEAD");
         assert!(!path.exists());
+
+        stdout
     }
 }
 